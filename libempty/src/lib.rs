@@ -1,172 +1,274 @@
 //! Sample and quite trivial implementation of the daimojo api
 //!
+//! Models loaded by this library are empty - they have no features and no outputs - except models loaded from
+//! files named `echo*`, which have one feature of each type and copy them to outputs of the same types,
+//! named with suffix `_out`.
+//! Like the real library, the model is only valid when a license is configured in environment variable
+//! `DRIVERLESS_AI_LICENSE_KEY` or `DRIVERLESS_AI_LICENSE_FILE` (pointing to an existing file).
+//! Tests running in parallel configure it through [EMPTY_SetLicenseKey] instead, as changing the environment races with them.
+//! The creation time of a model is its sequence number within the loaded instance of this library,
+//! so that copies of the library loaded side by side can be told apart.
 #![allow(non_snake_case)]
 #![allow(dead_code)]
 
-use std::ffi::c_char;
-use std::os::raw::c_uchar;
+use std::ffi::{c_char, CStr, CString};
+use std::path::Path;
 use std::ptr;
-use std::time::UNIX_EPOCH;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
-const VERSION: *const u8 = "2.99.99 EMPTY\0".as_ptr();
-const UUID: *const u8 = "00000000-0000-0000-0000-000000000000\0".as_ptr();
+const VERSION: &CStr = c"2.99.99 EMPTY";
+const UUID: &CStr = c"00000000-0000-0000-0000-000000000000";
+const DAI_VERSION: &CStr = c"0.0.0";
+
+/// Number of models created by this instance of the library.
+static MODELS_CREATED: AtomicU64 = AtomicU64::new(0);
+/// Whether a license key was set with [EMPTY_SetLicenseKey] in this instance of the library.
+static LICENSE_KEY_SET: AtomicBool = AtomicBool::new(false);
 
-pub type PCharArray = *const *const c_uchar;
 #[allow(non_camel_case_types)]
 #[repr(C)]
 #[derive(Copy,Clone,Debug)]
 pub enum MOJO_DataType {
-    MOJO_UNKNOWN = 1,
-    MOJO_FLOAT = 2,
-    MOJO_DOUBLE = 3,
-    MOJO_INT32 = 4,
-    MOJO_INT64 = 5,
+    MOJO_UNKNOWN = 0,
+    MOJO_BOOL = 1,
+    MOJO_INT32 = 2,
+    MOJO_INT64 = 3,
+    MOJO_FLOAT = 4,
+    MOJO_DOUBLE = 5,
     MOJO_STRING = 6,
 }
 
-#[no_mangle]
-extern "C" fn MOJO_Version() -> *const c_uchar {
-    VERSION
-}
-
 #[allow(non_camel_case_types)]
-pub struct MOJO_Model {}
+pub type MOJO_Transform_Ops = u64;
 
-#[allow(non_camel_case_types)]
-pub struct MOJO_Frame {}
+const PREDICT: MOJO_Transform_Ops = 1;
 
-#[allow(non_camel_case_types)]
-pub struct MOJO_Col {}
+/// Empty arrays still need a valid, aligned pointer.
+static NO_NAMES: [usize; 1] = [0];
+static NO_TYPES: [MOJO_DataType; 1] = [MOJO_DataType::MOJO_UNKNOWN];
 
+/// Array of C strings, which can be static as the strings are.
+struct Names<const N: usize>([*const c_char; N]);
+unsafe impl<const N: usize> Sync for Names<N> {}
 
-// added with command
-//  sed -n '/^    MOJO_\w\+:/{s:^    ::;s:,$::;s:^\(\w\+\).*fn\((.*$\):#[no_mangle] extern "C"\nfn \1\2 {\n    todo!()\n}\n:;p}' src/daimojo_library.rs >>libempty/src/lib.rs
-//  sed -n '/^    MOJO_\w\+:/{s:^    ::;s:,$::;s:^\(\w\+\).*fn\((.*$\):#[no_mangle] extern "C"\nfn \1\2 {\n    println!(" -----> called fn \1()");\n    todo!()\n}\n:;p}' src/daimojo_library.rs >>libempty/src/lib.rs
-//  sed -n '/^    MOJO_\w\+:/{s:^    ::;s:,$::;s:^\(\w\+\).*fn(\(.*$\):#[no_mangle] extern "C"\nfn \1(_\2 {\n    println!(" -----> called fn \1()");\n    todo!()\n}\n:;p}' src/daimojo_library.rs >>libempty/src/lib.rs
-#[no_mangle] extern "C"
-fn MOJO_NewModel(_filename: *const c_char, _tf_lib_prefix: *const c_char) -> *const MOJO_Model {
-    println!(" -----> called MOJO_NewModel");
-    ptr::null()
+const ECHO_COUNT: usize = 6;
+static ECHO_TYPES: [MOJO_DataType; ECHO_COUNT] = [
+    MOJO_DataType::MOJO_BOOL, MOJO_DataType::MOJO_INT32, MOJO_DataType::MOJO_INT64,
+    MOJO_DataType::MOJO_FLOAT, MOJO_DataType::MOJO_DOUBLE, MOJO_DataType::MOJO_STRING,
+];
+static ECHO_FEATURES: Names<ECHO_COUNT> = Names([
+    c"bool".as_ptr(), c"int32".as_ptr(), c"int64".as_ptr(), c"float".as_ptr(), c"double".as_ptr(), c"string".as_ptr(),
+]);
+static ECHO_OUTPUTS: Names<ECHO_COUNT> = Names([
+    c"bool_out".as_ptr(), c"int32_out".as_ptr(), c"int64_out".as_ptr(), c"float_out".as_ptr(), c"double_out".as_ptr(), c"string_out".as_ptr(),
+]);
+static ECHO_OPS: [MOJO_Transform_Ops; ECHO_COUNT] = [PREDICT; ECHO_COUNT];
+
+#[allow(non_camel_case_types)]
+#[repr(C)]
+pub struct MOJO_Model {
+    supported_ops: MOJO_Transform_Ops,
+    is_valid: bool,
+    uuid: *const c_char,
+    dai_version: *const c_char,
+    time_created: u64,
+    missing_values_count: usize,
+    missing_values: *const *const c_char,
+    feature_count: usize,
+    feature_names: *const *const c_char,
+    feature_types: *const MOJO_DataType,
 }
 
-#[no_mangle] extern "C"
-fn MOJO_DeleteModel(pipeline: *const MOJO_Model) {
-    println!(" -----> called fn MOJO_DeleteModel(pipeline=0x{:x})", pipeline as usize);
+#[allow(non_camel_case_types)]
+#[repr(C)]
+pub struct MOJO_Pipeline {
+    model: *const MOJO_Model,
+    operations: MOJO_Transform_Ops,
+    output_count: usize,
+    output_names: *const *const c_char,
+    output_types: *const MOJO_DataType,
+    output_ops: *const MOJO_Transform_Ops,
 }
 
-#[no_mangle] extern "C"
-fn MOJO_UUID(pipeline: *const MOJO_Model) -> *const c_uchar {
-    println!(" -----> called fn MOJO_UUID(pipeline=0x{:x})", pipeline as usize);
-    UUID
+#[allow(non_camel_case_types)]
+pub struct MOJO_Frame {
+    nrow: usize,
+    inputs: Vec<Column>,
+    outputs: Vec<Column>,
 }
 
-#[no_mangle] extern "C"
-fn MOJO_IsValid(pipeline: *const MOJO_Model) -> i32 {
-    println!(" -----> called fn MOJO_IsValid(pipeline=0x{:x})", pipeline as usize);
-    1
+/// Column buffer; fixed size values are 8-byte aligned, strings are passed as pointer to the vector.
+enum Column {
+    Fixed(Vec<u64>),
+    Strings(Vec<CString>),
 }
 
-#[no_mangle] extern "C"
-fn MOJO_TimeCreated(pipeline: *const MOJO_Model) -> u64 {
-    println!(" -----> called fn MOJO_TimeCreated(pipeline=0x{:x})", pipeline as usize);
-    std::time::SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+impl Column {
+    fn new(data_type: MOJO_DataType, nrow: usize) -> Self {
+        let width = match data_type {
+            MOJO_DataType::MOJO_BOOL => 1,
+            MOJO_DataType::MOJO_INT32 | MOJO_DataType::MOJO_FLOAT => 4,
+            MOJO_DataType::MOJO_INT64 | MOJO_DataType::MOJO_DOUBLE => 8,
+            MOJO_DataType::MOJO_STRING => return Column::Strings(vec![CString::default(); nrow]),
+            MOJO_DataType::MOJO_UNKNOWN => 0,
+        };
+        Column::Fixed(vec![0; (nrow * width).div_ceil(8)])
+    }
+
+    fn data(&mut self) -> *mut u8 {
+        match self {
+            Column::Fixed(data) => data.as_mut_ptr().cast(),
+            Column::Strings(strings) => (strings as *mut Vec<CString>).cast(),
+        }
+    }
 }
 
-#[no_mangle] extern "C"
-fn MOJO_MissingValuesNum(pipeline: *const MOJO_Model) -> usize {
-    println!(" -----> called fn MOJO_MissingValuesNum(pipeline=0x{:x})", pipeline as usize);
-    0
+fn columns(count: usize, types: *const MOJO_DataType, nrow: usize) -> Vec<Column> {
+    (0..count).map(|index| Column::new(unsafe { types.add(index).read() }, nrow)).collect()
 }
 
-#[no_mangle] extern "C"
-fn MOJO_MissingValues(pipeline: *const MOJO_Model) -> PCharArray {
-    println!(" -----> called fn MOJO_MissingValues(pipeline=0x{:x})", pipeline as usize);
-    ptr::null()
+fn license_configured() -> bool {
+    if LICENSE_KEY_SET.load(Ordering::Relaxed) {
+        return true;
+    }
+    if std::env::var_os("DRIVERLESS_AI_LICENSE_KEY").is_some() {
+        return true;
+    }
+    match std::env::var_os("DRIVERLESS_AI_LICENSE_FILE") {
+        Some(file) => std::path::Path::new(&file).is_file(),
+        None => false,
+    }
 }
 
+/// Configures a license key in this instance of the library, as if it was in the environment.
+/// Not part of the daimojo api.
 #[no_mangle] extern "C"
-fn MOJO_FeatureNum(pipeline: *const MOJO_Model) -> usize {
-    println!(" -----> called fn MOJO_FeatureNum(pipeline=0x{:x})", pipeline as usize);
-    0
+fn EMPTY_SetLicenseKey(key: *const c_char) {
+    LICENSE_KEY_SET.store(!key.is_null(), Ordering::Relaxed);
 }
 
-#[no_mangle] extern "C"
-fn MOJO_FeatureNames(pipeline: *const MOJO_Model) -> PCharArray {
-    println!(" -----> called fn MOJO_FeatureNames(pipeline=0x{:x})", pipeline as usize);
-    ptr::null()
+#[no_mangle]
+extern "C" fn MOJO_Version() -> *const c_char {
+    VERSION.as_ptr()
 }
 
 #[no_mangle] extern "C"
-fn MOJO_FeatureTypes(pipeline: *const MOJO_Model) -> *const MOJO_DataType {
-    println!(" -----> called fn MOJO_FeatureTypes(pipeline=0x{:x})", pipeline as usize);
-    ptr::null()
+fn MOJO_NewModel(filename: *const c_char, _tf_lib_prefix: *const c_char) -> *const MOJO_Model {
+    let filename = unsafe { CStr::from_ptr(filename) };
+    println!(" -----> called fn MOJO_NewModel(filename={filename:?})");
+    let echo = filename.to_str().ok()
+        .and_then(|filename| Path::new(filename).file_name())
+        .is_some_and(|name| name.to_string_lossy().starts_with("echo"));
+    let (feature_count, feature_names, feature_types) = match echo {
+        true => (ECHO_COUNT, ECHO_FEATURES.0.as_ptr(), ECHO_TYPES.as_ptr()),
+        false => (0, NO_NAMES.as_ptr().cast(), NO_TYPES.as_ptr()),
+    };
+    let model = MOJO_Model {
+        supported_ops: PREDICT,
+        is_valid: license_configured(),
+        uuid: UUID.as_ptr(),
+        dai_version: DAI_VERSION.as_ptr(),
+        time_created: MODELS_CREATED.fetch_add(1, Ordering::Relaxed) + 1,
+        missing_values_count: 0,
+        missing_values: NO_NAMES.as_ptr().cast(),
+        feature_count,
+        feature_names,
+        feature_types,
+    };
+    Box::into_raw(Box::new(model))
 }
 
 #[no_mangle] extern "C"
-fn MOJO_OutputNum(pipeline: *const MOJO_Model) -> usize {
-    println!(" -----> called fn MOJO_OutputNum(pipeline=0x{:x})", pipeline as usize);
-    0
+fn MOJO_DeleteModel(model: *const MOJO_Model) {
+    println!(" -----> called fn MOJO_DeleteModel(model=0x{:x})", model as usize);
+    drop(unsafe { Box::from_raw(model as *mut MOJO_Model) });
 }
 
 #[no_mangle] extern "C"
-fn MOJO_OutputNames(pipeline: *const MOJO_Model) -> PCharArray {
-    println!(" -----> called fn MOJO_OutputNames(pipeline=0x{:x})", pipeline as usize);
-    ptr::null()
+fn MOJO_NewPipeline(model: *const MOJO_Model, flags: MOJO_Transform_Ops) -> *const MOJO_Pipeline {
+    println!(" -----> called fn MOJO_NewPipeline(model=0x{:x}, flags={flags})", model as usize);
+    if flags & PREDICT == 0 {
+        return ptr::null();
+    }
+    let echo = unsafe { (*model).feature_count } == ECHO_COUNT;
+    let pipeline = MOJO_Pipeline {
+        model,
+        operations: flags,
+        output_count: if echo { ECHO_COUNT } else { 0 },
+        output_names: if echo { ECHO_OUTPUTS.0.as_ptr() } else { NO_NAMES.as_ptr().cast() },
+        output_types: if echo { ECHO_TYPES.as_ptr() } else { NO_TYPES.as_ptr() },
+        output_ops: if echo { ECHO_OPS.as_ptr() } else { NO_NAMES.as_ptr().cast() },
+    };
+    Box::into_raw(Box::new(pipeline))
 }
 
 #[no_mangle] extern "C"
-fn MOJO_OutputTypes(pipeline: *const MOJO_Model) -> *const MOJO_DataType {
-    println!(" -----> called fn MOJO_OutputTypes(pipeline=0x{:x})", pipeline as usize);
-    ptr::null()
+fn MOJO_DeletePipeline(pipeline: *const MOJO_Pipeline) {
+    println!(" -----> called fn MOJO_DeletePipeline(pipeline=0x{:x})", pipeline as usize);
+    drop(unsafe { Box::from_raw(pipeline as *mut MOJO_Pipeline) });
 }
 
 #[no_mangle] extern "C"
-fn MOJO_Predict(pipeline: *const MOJO_Model, frame: *const MOJO_Frame) {
-    println!(" -----> called fn MOJO_Predict(pipeline=0x{:x}, frame=0x{:x})", pipeline as usize, frame as usize);
+fn MOJO_Transform(pipeline: *const MOJO_Pipeline, frame: *const MOJO_Frame, nrow: usize, debug: bool) {
+    println!(" -----> called fn MOJO_Transform(pipeline=0x{:x}, frame=0x{:x}, nrow={nrow}, debug={debug})", pipeline as usize, frame as usize);
+    let frame = unsafe { &mut *(frame as *mut MOJO_Frame) };
+    // in place, as the output buffers were handed out before
+    for (output, input) in frame.outputs.iter_mut().zip(&frame.inputs) {
+        match (output, input) {
+            (Column::Fixed(output), Column::Fixed(input)) => output.copy_from_slice(input),
+            (Column::Strings(output), Column::Strings(input)) => output.clone_from_slice(input),
+            _ => unreachable!("outputs have the types of inputs"),
+        }
+    }
 }
 
 #[no_mangle] extern "C"
-fn MOJO_NewFrame(_cols: *const *const MOJO_Col, _names: PCharArray, count: usize) -> *const MOJO_Frame {
-    println!(" -----> called fn MOJO_NewFrame(... count={count})");
-    ptr::null()
+fn MOJO_Pipeline_NewFrame(pipeline: *const MOJO_Pipeline, nrow: usize) -> *const MOJO_Frame {
+    println!(" -----> called fn MOJO_Pipeline_NewFrame(pipeline=0x{:x}, nrow={nrow})", pipeline as usize);
+    let (pipeline, model) = unsafe { (&*pipeline, &*(*pipeline).model) };
+    let inputs = columns(model.feature_count, model.feature_types, nrow);
+    let outputs = columns(pipeline.output_count, pipeline.output_types, nrow);
+    Box::into_raw(Box::new(MOJO_Frame { nrow, inputs, outputs }))
 }
 
 #[no_mangle] extern "C"
 fn MOJO_DeleteFrame(frame: *const MOJO_Frame) {
     println!(" -----> called fn MOJO_DeleteFrame(frame=0x{:x})", frame as usize);
+    drop(unsafe { Box::from_raw(frame as *mut MOJO_Frame) });
 }
 
 #[no_mangle] extern "C"
 fn MOJO_FrameNcol(frame: *const MOJO_Frame) -> usize {
     println!(" -----> called fn MOJO_FrameNcol(frame=0x{:x})", frame as usize);
-    0
-}
-
-#[no_mangle] extern "C"
-fn MOJO_GetColByName(frame: *const MOJO_Frame, name: *const c_uchar) -> *const MOJO_Col {
-    println!(" -----> called fn MOJO_GetColByName(frame=0x{:x},name='{name:?}')", frame as usize);
-    ptr::null()
+    let frame = unsafe { &*frame };
+    frame.inputs.len() + frame.outputs.len()
 }
 
 #[no_mangle] extern "C"
-fn MOJO_NewCol(datatype: MOJO_DataType, size: usize, data: *mut u8) -> *const MOJO_Col {
-    println!(" -----> called fn MOJO_NewCol(type={datatype:?}, size={size}, data=0x{:x})", data as usize);
-    ptr::null()
+fn MOJO_Input_Data(_pipeline: *const MOJO_Pipeline, frame: *const MOJO_Frame, index: usize) -> *mut u8 {
+    println!(" -----> called fn MOJO_Input_Data(index={index})");
+    let frame = unsafe { &mut *(frame as *mut MOJO_Frame) };
+    frame.inputs.get_mut(index).map_or(ptr::null_mut(), Column::data)
 }
 
 #[no_mangle] extern "C"
-fn MOJO_DeleteCol(col: *const MOJO_Col) {
-    println!(" -----> called fn MOJO_DeleteCol(col=0x{:x})", col as usize);
+fn MOJO_Output_Data(_pipeline: *const MOJO_Pipeline, frame: *const MOJO_Frame, index: usize) -> *const u8 {
+    println!(" -----> called fn MOJO_Output_Data(index={index})");
+    let frame = unsafe { &mut *(frame as *mut MOJO_Frame) };
+    frame.outputs.get_mut(index).map_or(ptr::null(), |column| column.data().cast_const())
 }
 
+/// `buffer` is the vector of a string column, see [Column::data].
 #[no_mangle] extern "C"
-fn MOJO_Type(col: *const MOJO_Col) -> MOJO_DataType {
-    println!(" -----> called fn MOJO_Type(col=0x{:x})", col as usize);
-    MOJO_DataType::MOJO_UNKNOWN
+fn MOJO_Column_Write_Str(buffer: *mut u8, index: usize, value: *const c_char) {
+    println!(" -----> called fn MOJO_Column_Write_Str(index={index})");
+    let strings = unsafe { &mut *buffer.cast::<Vec<CString>>() };
+    strings[index] = unsafe { CStr::from_ptr(value) }.to_owned();
 }
 
 #[no_mangle] extern "C"
-fn MOJO_Data(col: *const MOJO_Col) -> *mut u8 {
-    println!(" -----> called fn MOJO_Data(col=0x{:x})", col as usize);
-    ptr::null_mut()
+fn MOJO_Column_Read_Str(buffer: *const u8, index: usize) -> *const c_char {
+    println!(" -----> called fn MOJO_Column_Read_Str(index={index})");
+    let strings = unsafe { &*buffer.cast::<Vec<CString>>() };
+    strings[index].as_ptr()
 }
@@ -21,5 +21,5 @@
 #[allow(non_snake_case)]
 #[no_mangle]
 pub extern "C" fn MOJO_Version() -> *const u8 {
-    "JUST VERSION\0".as_ptr()
+    c"JUST VERSION".as_ptr().cast()
 }
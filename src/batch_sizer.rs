@@ -0,0 +1,44 @@
+//! Size of the batches scored in one frame, fixed or adapted while scoring
+
+use std::time::Duration;
+
+/// Chooses how many rows go into each batch, up to the capacity of the frame.
+pub trait BatchSizer {
+    /// Rows of the frame, the largest batch
+    fn capacity(&self) -> usize;
+
+    /// Size of the next batch
+    fn current(&self) -> usize;
+
+    /// Record the transform time of a batch and return the size of the next one.
+    fn update(&mut self, rows: usize, elapsed: Duration) -> usize;
+}
+
+/// Fixed batch size.
+impl BatchSizer for usize {
+    fn capacity(&self) -> usize {
+        *self
+    }
+
+    fn current(&self) -> usize {
+        *self
+    }
+
+    fn update(&mut self, _rows: usize, _elapsed: Duration) -> usize {
+        *self
+    }
+}
+
+impl<B: BatchSizer + ?Sized> BatchSizer for &mut B {
+    fn capacity(&self) -> usize {
+        (**self).capacity()
+    }
+
+    fn current(&self) -> usize {
+        (**self).current()
+    }
+
+    fn update(&mut self, rows: usize, elapsed: Duration) -> usize {
+        (**self).update(rows, elapsed)
+    }
+}
@@ -0,0 +1,194 @@
+//! Automatic batch size: estimated from a sample of the input and the frame memory per row,
+//! then adapted while scoring, based on measured transform time
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::time::Duration;
+use daimojo::{BatchSizer, MOJO_DataType, RawPipeline};
+
+/// Default limit of memory taken by one frame.
+pub const DEFAULT_MAX_MEMORY: &str = "256MiB";
+/// Number of input rows sampled by [InputSample::read].
+const SAMPLE_ROWS: usize = 100;
+/// Batch size used when nothing is known about the input.
+const FALLBACK_BATCH_SIZE: usize = 10_000;
+/// First batch size of [AdaptiveBatch], grown from there as long as batches are fast enough.
+pub const INITIAL_BATCH_SIZE: usize = 1000;
+/// Transform time of one batch that [AdaptiveBatch] aims at.
+pub const TARGET_BATCH_TIME: Duration = Duration::from_millis(500);
+
+/// Parse memory size like `512M`, `2GiB` or `1000000`; units are powers of 1024.
+pub fn parse_memory(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(digits);
+    let number: u64 = number.parse().map_err(|_| format!("invalid memory size '{s}'"))?;
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("unknown unit in '{s}', expected one of: K, M, G, T")),
+    };
+    number.checked_mul(1 << shift).ok_or_else(|| format!("memory size '{s}' is too large"))
+}
+
+/// Bytes taken by one value in a frame column; strings are counted as the pointer only.
+fn value_size(data_type: MOJO_DataType) -> usize {
+    match data_type {
+        MOJO_DataType::MOJO_BOOL => 1,
+        MOJO_DataType::MOJO_INT32 | MOJO_DataType::MOJO_FLOAT => 4,
+        MOJO_DataType::MOJO_INT64 | MOJO_DataType::MOJO_DOUBLE => 8,
+        MOJO_DataType::MOJO_STRING => size_of::<*const u8>(),
+        MOJO_DataType::MOJO_UNKNOWN => 0,
+    }
+}
+
+/// First rows of the input, measured.
+#[derive(Debug, Default, PartialEq)]
+pub struct InputSample {
+    pub rows: usize,
+    /// Average length of a CSV row, including the line end
+    pub row_len: f64,
+    /// Average length of the string features of a row, including their terminating NULs
+    pub string_len: f64,
+}
+
+impl InputSample {
+    /// Read up to [SAMPLE_ROWS] rows of CSV input.
+    pub fn read<R: Read>(pipeline: &RawPipeline, input: R) -> csv::Result<Self> {
+        let mut rdr = csv::Reader::from_reader(input);
+        let headers: HashMap<Vec<u8>, usize> = rdr.byte_headers()?.iter().enumerate()
+            .map(|(index, name)| (name.to_vec(), index))
+            .collect();
+        let start = rdr.position().byte();
+        let string_columns: Vec<usize> = pipeline.model.features()
+            .filter(|(_, data_type)| *data_type == MOJO_DataType::MOJO_STRING)
+            .filter_map(|(name, _)| headers.get(name.as_bytes()).copied())
+            .collect();
+        let mut sample = Self::default();
+        let mut string_bytes = 0;
+        let mut record = csv::ByteRecord::new();
+        while sample.rows < SAMPLE_ROWS && rdr.read_byte_record(&mut record)? {
+            sample.rows += 1;
+            string_bytes += string_columns.iter()
+                .map(|&index| record.get(index).map_or(0, <[u8]>::len) + 1)
+                .sum::<usize>();
+        }
+        if sample.rows > 0 {
+            sample.row_len = (rdr.position().byte() - start) as f64 / sample.rows as f64;
+            sample.string_len = string_bytes as f64 / sample.rows as f64;
+        }
+        Ok(sample)
+    }
+}
+
+/// Frame memory per row: values of all features and outputs, plus the sampled string contents.
+pub fn row_memory(pipeline: &RawPipeline, sample: &InputSample) -> usize {
+    let values: usize = pipeline.model.feature_types().iter()
+        .chain(pipeline.output_types())
+        .map(|&data_type| value_size(data_type))
+        .sum();
+    values + sample.string_len.ceil() as usize
+}
+
+/// Largest useful batch: the whole input if it fits into `max_memory`, otherwise as much as fits.
+/// `input_len` is the size of the input in bytes, when known.
+pub fn estimate(input_len: Option<u64>, sample: &InputSample, row_memory: usize, max_memory: u64) -> usize {
+    let by_memory = (max_memory / row_memory.max(1) as u64).max(1) as usize;
+    let by_input = match input_len {
+        Some(len) if sample.row_len > 0.0 => (len as f64 / sample.row_len).ceil() as usize,
+        Some(_) => 1,
+        None => FALLBACK_BATCH_SIZE,
+    };
+    by_input.min(by_memory).max(1)
+}
+
+/// Batch size adapted to measured transform time, up to the frame capacity.
+/// It starts small and grows while batches take less than the target time, and shrinks when they take more.
+#[derive(Debug)]
+pub struct AdaptiveBatch {
+    capacity: usize,
+    current: usize,
+    target: Duration,
+}
+
+impl AdaptiveBatch {
+    pub fn new(capacity: usize, target: Duration) -> Self {
+        Self { capacity, current: capacity.min(INITIAL_BATCH_SIZE), target }
+    }
+}
+
+impl BatchSizer for AdaptiveBatch {
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn current(&self) -> usize {
+        self.current
+    }
+
+    fn update(&mut self, rows: usize, elapsed: Duration) -> usize {
+        // only full batches tell how a batch of the current size performs
+        if rows < self.current {
+            return self.current;
+        }
+        let per_row = elapsed.as_secs_f64() / rows as f64;
+        let ideal = if per_row > 0.0 { self.target.as_secs_f64() / per_row } else { f64::INFINITY };
+        // at most double or halve at once, to smooth out noise in the measurements
+        let next = ideal.clamp(self.current as f64 / 2.0, self.current as f64 * 2.0) as usize;
+        let next = next.clamp(1, self.capacity);
+        if next != self.current {
+            log::debug!("Batch size adapted from {} to {next}, as {rows} rows took {elapsed:?}", self.current);
+            self.current = next;
+        }
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use daimojo::BatchSizer;
+    use super::{estimate, parse_memory, AdaptiveBatch, InputSample};
+
+    #[test]
+    fn memory_sizes() {
+        assert_eq!(Ok(1000), parse_memory("1000"));
+        assert_eq!(Ok(512 << 20), parse_memory("512M"));
+        assert_eq!(Ok(2 << 30), parse_memory("2GiB"));
+        assert_eq!(Ok(4 << 10), parse_memory("4 kb"));
+        assert!(parse_memory("12X").is_err());
+        assert!(parse_memory("M").is_err());
+    }
+
+    #[test]
+    fn estimates() {
+        let sample = InputSample { rows: 100, row_len: 50.0, string_len: 0.0 };
+        // whole input fits
+        assert_eq!(2000, estimate(Some(100_000), &sample, 40, 1 << 20));
+        // limited by memory
+        assert_eq!(1000, estimate(Some(100_000), &sample, 40, 40_000));
+        assert_eq!(1, estimate(Some(100_000), &sample, 40, 10));
+        assert_eq!(10_000, estimate(None, &sample, 40, 1 << 30));
+        assert_eq!(1, estimate(Some(0), &InputSample::default(), 40, 1 << 30));
+    }
+
+    #[test]
+    fn adaptive() {
+        let mut batch = AdaptiveBatch::new(10_000, Duration::from_millis(100));
+        assert_eq!(1000, batch.current());
+        // fast: grows at most twice
+        assert_eq!(2000, batch.update(1000, Duration::from_millis(1)));
+        // partial batch: no change
+        assert_eq!(2000, batch.update(5, Duration::from_secs(1)));
+        // slow: shrinks at most by half
+        assert_eq!(1000, batch.update(2000, Duration::from_secs(10)));
+        // close to the target
+        assert_eq!(1250, batch.update(1000, Duration::from_millis(80)));
+        // never above capacity
+        let mut batch = AdaptiveBatch::new(1500, Duration::from_millis(100));
+        assert_eq!(1500, batch.update(1000, Duration::ZERO));
+    }
+}
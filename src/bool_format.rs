@@ -0,0 +1,115 @@
+//! Text representation of boolean values in CSV
+//!
+//! Boolean columns are stored as one byte, 0 for false and 1 for true, and have no NA;
+//! missing values are written as [BoolFormat::na_value].
+
+/// Tokens recognized on import and rendered on export. Import matching ignores case.
+#[derive(Clone, Debug)]
+pub struct BoolFormat {
+    pub true_tokens: Vec<String>,
+    pub false_tokens: Vec<String>,
+    pub na_tokens: Vec<String>,
+    /// Value stored for NA and for unrecognized tokens
+    pub na_value: bool,
+    /// Rendering on export
+    pub true_output: String,
+    pub false_output: String,
+}
+
+impl Default for BoolFormat {
+    fn default() -> Self {
+        let tokens = |tokens: &[&str]| tokens.iter().map(|t| t.to_string()).collect();
+        Self {
+            true_tokens: tokens(&["true", "1", "1.0", "yes", "y", "t"]),
+            false_tokens: tokens(&["false", "0", "0.0", "no", "n", "f"]),
+            na_tokens: tokens(&["", "NA", "N/A", "null", "NaN"]),
+            na_value: false,
+            true_output: "true".to_string(),
+            false_output: "false".to_string(),
+        }
+    }
+}
+
+impl BoolFormat {
+    /// Set rendering on export, like `1:0` or `yes:no`.
+    pub fn with_output(mut self, true_output: &str, false_output: &str) -> Self {
+        self.true_output = true_output.to_string();
+        self.false_output = false_output.to_string();
+        self
+    }
+
+    /// Replace the tokens recognized on import; `None` keeps the current ones.
+    pub fn with_tokens(mut self, true_tokens: Option<Vec<String>>, false_tokens: Option<Vec<String>>, na_tokens: Option<Vec<String>>) -> Self {
+        self.true_tokens = true_tokens.unwrap_or(self.true_tokens);
+        self.false_tokens = false_tokens.unwrap_or(self.false_tokens);
+        self.na_tokens = na_tokens.unwrap_or(self.na_tokens);
+        self
+    }
+
+    /// Parse the token; `None` for NA and unrecognized tokens, which are logged.
+    pub fn parse(&self, s: &str) -> Option<bool> {
+        self.parse_strict(s).unwrap_or_else(|| {
+            log::warn!("Invalid bool value: '{}', read as {}", s.trim(), self.na_value);
+            None
+        })
+    }
+
+    /// Parse the token without logging; `Some(None)` for NA, `None` for unrecognized tokens.
+    pub fn parse_strict(&self, s: &str) -> Option<Option<bool>> {
+        let s = s.trim();
+        let matches = |tokens: &[String]| tokens.iter().any(|token| token.eq_ignore_ascii_case(s));
+        if matches(&self.true_tokens) {
+            Some(Some(true))
+        } else if matches(&self.false_tokens) {
+            Some(Some(false))
+        } else if matches(&self.na_tokens) {
+            Some(None)
+        } else {
+            None
+        }
+    }
+
+    /// Parse the token as a value to be stored, see [BoolFormat::na_value].
+    pub fn parse_or_na(&self, s: &str) -> bool {
+        self.parse(s).unwrap_or(self.na_value)
+    }
+
+    pub fn render(&self, value: bool) -> &str {
+        if value { &self.true_output } else { &self.false_output }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BoolFormat;
+
+    #[test]
+    fn tokens() {
+        let format = BoolFormat::default();
+        for token in ["true", "TRUE", "1", "1.0", "yes", "Y", "t"] {
+            assert_eq!(Some(true), format.parse(token), "{token}");
+        }
+        for token in ["false", "False", "0", "0.0", "no", "N", "f"] {
+            assert_eq!(Some(false), format.parse(token), "{token}");
+        }
+        for token in ["", "NA", "null", "maybe"] {
+            assert_eq!(None, format.parse(token), "{token}");
+        }
+        assert!(!format.parse_or_na("NA"));
+        assert!(!format.parse_or_na("maybe"));
+        assert_eq!(Some(Some(true)), format.parse_strict(" yes "));
+        assert_eq!(Some(None), format.parse_strict("NA"));
+        assert_eq!(None, format.parse_strict("maybe"));
+        let format = BoolFormat { na_value: true, ..Default::default() }.with_output("1", "0");
+        assert!(format.parse_or_na("NA"));
+        assert_eq!("1", format.render(true));
+        assert_eq!("0", format.render(false));
+
+        let tokens = |tokens: &[&str]| Some(tokens.iter().map(|t| t.to_string()).collect());
+        let format = BoolFormat::default().with_tokens(tokens(&["ja"]), tokens(&["nein"]), None);
+        assert_eq!(Some(true), format.parse("JA"));
+        assert_eq!(Some(false), format.parse("nein"));
+        assert_eq!(None, format.parse("yes"));
+        assert_eq!(None, format.parse("NA"));
+    }
+}
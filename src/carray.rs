@@ -15,6 +15,7 @@ pub struct CArrayIterator<T> {
 }
 
 impl<T> CArrayIterator<T> {
+    #[allow(clippy::new_ret_no_self)]
     pub(crate) fn new(ptr: *const T, count: usize) -> impl Iterator<Item=T> {
         Self { ptr, count }
     }
@@ -50,6 +51,7 @@ pub struct CTwinArrayIterator<T1, T2> {
 }
 
 impl<T1, T2> CTwinArrayIterator<T1, T2> {
+    #[allow(clippy::new_ret_no_self)]
     pub(crate) fn new(count: usize, ptr1: *const T1, ptr2: *const T2) -> impl Iterator<Item=(T1, T2)> {
         CTwinArrayIterator { ptr1, ptr2, count, }
     }
@@ -0,0 +1,236 @@
+use std::sync::{Barrier, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use daimojo::{DaiMojoLibrary, FrameExporter, FrameImporter, MOJO_Transform_Ops, RawFrame, RawModel, RawPipeline};
+use crate::cmd_gen_input::InputGenerator;
+
+/// How CSV input is read into frames.
+#[derive(Clone, Copy, Debug, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportPath {
+    /// [FrameImporter::import_frame] with string records, allocated and validated as UTF-8 per row
+    Records,
+    /// [FrameImporter::import_batch] with one reused byte record
+    Bytes,
+}
+
+impl ImportPath {
+    fn name(&self) -> &'static str {
+        match self {
+            ImportPath::Records => "records",
+            ImportPath::Bytes => "bytes",
+        }
+    }
+}
+
+/// Combinations to measure; every import path with every thread count and batch size.
+pub struct Sweep<'s> {
+    pub importers: &'s [ImportPath],
+    pub batch_sizes: &'s [usize],
+    pub threads: &'s [usize],
+}
+
+/// Measurements for one combination of import path, batch size and thread count.
+#[derive(Serialize)]
+struct BenchResult {
+    importer: ImportPath,
+    batch_size: usize,
+    threads: usize,
+    rows: usize,
+    batches: usize,
+    wall_secs: f64,
+    rows_per_sec: f64,
+    /// Summary of all threads, it can exceed the wall time
+    import_secs: f64,
+    transform_secs: f64,
+    export_secs: f64,
+    /// Latency of `pipeline.transform` per batch, in microseconds
+    latency_us: Percentiles,
+}
+
+#[derive(Serialize)]
+struct Percentiles {
+    min: f64,
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+#[derive(Serialize)]
+struct BenchReport {
+    library_version: String,
+    model_uuid: String,
+    input_rows: usize,
+    results: Vec<BenchResult>,
+}
+
+#[derive(Default)]
+struct ThreadTimes {
+    rows: usize,
+    import: Duration,
+    transform: Duration,
+    export: Duration,
+    latencies: Vec<Duration>,
+}
+
+pub fn cmd_bench(lib: &DaiMojoLibrary, mojo: &str, input: Option<String>, rows: usize, sweep: &Sweep, json: Option<String>) -> anyhow::Result<u8> {
+    let model = RawModel::load(lib, mojo, ".")?;
+    let data = match input {
+        Some(input) => std::fs::read(input)?,
+        None => InputGenerator::default().write_csv(&model, rows, Vec::new())?,
+    };
+    let input_rows = csv::Reader::from_reader(data.as_slice()).byte_records().count();
+    log::info!("Benchmarking with {input_rows} input rows");
+
+    let mut results = Vec::new();
+    for &importer in sweep.importers {
+        for &thread_count in sweep.threads {
+            for &batch_size in sweep.batch_sizes {
+                let result = bench_one(lib, mojo, &data, importer, batch_size, thread_count)?;
+                if json.is_none() {
+                    println!("importer={:<7} batch={:<7} threads={:<3} rows/s={:<12.1} import={:.3}s transform={:.3}s export={:.3}s latency[us]: p50={:.1} p90={:.1} p99={:.1} max={:.1}",
+                             result.importer.name(), result.batch_size, result.threads, result.rows_per_sec,
+                             result.import_secs, result.transform_secs, result.export_secs,
+                             result.latency_us.p50, result.latency_us.p90, result.latency_us.p99, result.latency_us.max);
+                }
+                results.push(result);
+            }
+        }
+    }
+    if let Some(json) = json {
+        let report = BenchReport {
+            library_version: lib.version().to_string(),
+            model_uuid: model.uuid().to_string_lossy().to_string(),
+            input_rows,
+            results,
+        };
+        let file = std::fs::File::create(&json)?;
+        serde_json::to_writer_pretty(file, &report)?;
+        log::info!("Benchmark report written to {json}");
+    }
+    Ok(0)
+}
+
+fn bench_one(lib: &DaiMojoLibrary, mojo: &str, data: &[u8], importer: ImportPath, batch_size: usize, thread_count: usize) -> anyhow::Result<BenchResult> {
+    let loading = Mutex::new(());
+    let ready = Barrier::new(thread_count + 1);
+    let (times, wall) = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..thread_count)
+            .map(|_| scope.spawn(|| score_thread(lib, mojo, data, importer, batch_size, &loading, &ready)))
+            .collect();
+        // the clock starts once every thread has loaded its model
+        ready.wait();
+        let start = Instant::now();
+        let times = handles.into_iter()
+            .map(|h| h.join().expect("benchmark thread panicked"))
+            .collect::<anyhow::Result<Vec<ThreadTimes>>>();
+        (times, start.elapsed())
+    });
+    let times = times?;
+
+    let rows = times.iter().map(|t| t.rows).sum();
+    let mut latencies: Vec<Duration> = times.iter().flat_map(|t| t.latencies.iter().copied()).collect();
+    latencies.sort();
+    Ok(BenchResult {
+        importer,
+        batch_size,
+        threads: thread_count,
+        rows,
+        batches: latencies.len(),
+        wall_secs: wall.as_secs_f64(),
+        rows_per_sec: rows as f64 / wall.as_secs_f64(),
+        import_secs: times.iter().map(|t| t.import.as_secs_f64()).sum(),
+        transform_secs: times.iter().map(|t| t.transform.as_secs_f64()).sum(),
+        export_secs: times.iter().map(|t| t.export.as_secs_f64()).sum(),
+        latency_us: Percentiles {
+            min: percentile(&latencies, 0.0),
+            p50: percentile(&latencies, 0.5),
+            p90: percentile(&latencies, 0.9),
+            p99: percentile(&latencies, 0.99),
+            max: percentile(&latencies, 1.0),
+        },
+    })
+}
+
+/// Load a model instance of the calling thread, which keeps it with its pipeline and frame, then score whole input.
+/// Threads load one at a time, and wait at `ready` until all have loaded, or failed to.
+fn score_thread(lib: &DaiMojoLibrary, mojo: &str, data: &[u8], import_path: ImportPath, batch_size: usize, loading: &Mutex<()>, ready: &Barrier) -> anyhow::Result<ThreadTimes> {
+    let mut start = StartGate(Some(ready));
+    let turn = loading.lock().unwrap_or_else(PoisonError::into_inner);
+    let model = RawModel::load(lib, mojo, ".")?;
+    let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops)?;
+    let frame = RawFrame::new(&pipeline, batch_size)?;
+    drop(turn);
+    start.pass();
+    score_all(&pipeline, frame, data, import_path)
+}
+
+/// Waits at the barrier when passed, or when dropped before, so that a thread failing to load does not block the others.
+struct StartGate<'b>(Option<&'b Barrier>);
+
+impl StartGate<'_> {
+    fn pass(&mut self) {
+        if let Some(barrier) = self.0.take() {
+            barrier.wait();
+        }
+    }
+}
+
+impl Drop for StartGate<'_> {
+    fn drop(&mut self) {
+        self.pass();
+    }
+}
+
+/// Score whole input with the thread's own pipeline and frame, measuring each phase separately.
+fn score_all(pipeline: &RawPipeline, frame: RawFrame, data: &[u8], import_path: ImportPath) -> anyhow::Result<ThreadTimes> {
+    let mut rdr = csv::Reader::from_reader(data);
+    let mut importer = FrameImporter::init(pipeline, &frame, &mut rdr)?;
+    let mut exporter = FrameExporter::with_writer(pipeline, &frame, std::io::sink())?;
+    let mut times = ThreadTimes::default();
+    loop {
+        let t0 = Instant::now();
+        let rows = match import_path {
+            ImportPath::Records => importer.import_frame(&mut rdr.records())?,
+            ImportPath::Bytes => importer.import_batch(&mut rdr)?,
+        };
+        let Some(rows) = rows else { break };
+        let t1 = Instant::now();
+        pipeline.transform(&frame, rows, false)?;
+        let t2 = Instant::now();
+        exporter.export_frame(rows)?;
+        let t3 = Instant::now();
+        times.rows += rows;
+        times.import += t1 - t0;
+        times.transform += t2 - t1;
+        times.export += t3 - t2;
+        times.latencies.push(t2 - t1);
+    }
+    Ok(times)
+}
+
+/// Value at given quantile of sorted durations, in microseconds.
+fn percentile(sorted: &[Duration], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let index = ((sorted.len() - 1) as f64 * q).round() as usize;
+    sorted[index].as_secs_f64() * 1e6
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::percentile;
+
+    #[test]
+    fn percentiles() {
+        let sorted: Vec<Duration> = (1..=101).map(Duration::from_micros).collect();
+        assert_eq!(1.0, percentile(&sorted, 0.0));
+        assert_eq!(51.0, percentile(&sorted, 0.5));
+        assert_eq!(91.0, percentile(&sorted, 0.9));
+        assert_eq!(101.0, percentile(&sorted, 1.0));
+        assert_eq!(0.0, percentile(&[], 0.5));
+    }
+}
@@ -0,0 +1,147 @@
+use std::process::{Command, Stdio};
+use anyhow::bail;
+use daimojo::{DaiMojoLibrary, FrameExporter, FrameImporter, MOJO_Transform_Ops, RawFrame, RawModel, RawPipeline};
+use daimojo::version::VersionRange;
+
+const BATCH_SIZE: usize = 1000;
+
+/// How the two runtimes are kept apart.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Isolation {
+    /// Both libraries loaded into this process, each with `RTLD_LOCAL`
+    InProcess,
+    /// Each library used by its own worker process, running `predict` of this executable
+    Workers,
+}
+
+/// Differences found in one output column.
+#[derive(Default)]
+struct ColumnDiff {
+    differing: usize,
+    max_abs_diff: f64,
+}
+
+pub fn cmd_compare_runtimes(lib_a: &str, lib_b: &str, mojo: &str, accepted: &VersionRange, input: &str, tolerance: f64, isolation: Isolation) -> anyhow::Result<u8> {
+    let (output_a, output_b) = match isolation {
+        Isolation::InProcess => {
+            let a = DaiMojoLibrary::load_accepting(lib_a, accepted)?;
+            let b = DaiMojoLibrary::load_accepting(lib_b, accepted)?;
+            println!("* Runtime A: {} ({lib_a})", a.runtime_version());
+            println!("* Runtime B: {} ({lib_b})", b.runtime_version());
+            (predict_in_process(&a, mojo, input)?, predict_in_process(&b, mojo, input)?)
+        }
+        Isolation::Workers => {
+            println!("* Runtime A: {lib_a}");
+            println!("* Runtime B: {lib_b}");
+            (predict_in_worker("a", lib_a, mojo, accepted, input)?, predict_in_worker("b", lib_b, mojo, accepted, input)?)
+        }
+    };
+    compare(&output_a, &output_b, tolerance)
+}
+
+fn predict_in_process(lib: &DaiMojoLibrary, mojo: &str, input: &str) -> anyhow::Result<Vec<u8>> {
+    let model = RawModel::load(lib, mojo, ".")?;
+    let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops)?;
+    let frame = RawFrame::new(&pipeline, BATCH_SIZE)?;
+    let mut rdr = csv::Reader::from_path(input)?;
+    let mut importer = FrameImporter::init(&pipeline, &frame, &mut rdr)?;
+    let mut output = Vec::new();
+    let mut exporter = FrameExporter::with_writer(&pipeline, &frame, &mut output)?;
+    while let Some(rows) = importer.import_batch(&mut rdr)? {
+        pipeline.transform(&frame, rows, false)?;
+        exporter.export_frame(rows)?;
+    }
+    exporter.flush()?;
+    drop(exporter);
+    Ok(output)
+}
+
+/// Run `predict` in a child process, which inherits the license settings of this one.
+/// The output goes through a temporary file, as the library itself may write to stdout.
+fn predict_in_worker(name: &str, lib: &str, mojo: &str, accepted: &VersionRange, input: &str) -> anyhow::Result<Vec<u8>> {
+    let exe = std::env::current_exe()?;
+    let output = std::env::temp_dir().join(format!("daimojo-compare-{}-{name}.csv", std::process::id()));
+    log::debug!("Starting worker for {lib}");
+    let status = Command::new(exe)
+        .args(["--silent", "--lib", lib, "--mojo", mojo, "--runtime-version", &accepted.to_string()])
+        .args(["predict", "--batch", &BATCH_SIZE.to_string(), "--out"])
+        .arg(&output)
+        .arg(input)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .status()?;
+    let data = std::fs::read(&output);
+    std::fs::remove_file(&output).ok();
+    if !status.success() {
+        bail!("Worker for {lib} failed with {status}");
+    }
+    Ok(data?)
+}
+
+fn compare(output_a: &[u8], output_b: &[u8], tolerance: f64) -> anyhow::Result<u8> {
+    let mut rdr_a = csv::Reader::from_reader(output_a);
+    let mut rdr_b = csv::Reader::from_reader(output_b);
+    let headers = rdr_a.headers()?.clone();
+    if &headers != rdr_b.headers()? {
+        println!("* Output columns differ:");
+        println!("  A: {}", headers.iter().collect::<Vec<_>>().join(", "));
+        println!("  B: {}", rdr_b.headers()?.iter().collect::<Vec<_>>().join(", "));
+        return Ok(1);
+    }
+    let mut diffs: Vec<ColumnDiff> = headers.iter().map(|_| ColumnDiff::default()).collect();
+    let mut records_a = rdr_a.records();
+    let mut records_b = rdr_b.records();
+    let mut rows = 0;
+    let mut differing_rows = 0;
+    loop {
+        let (a, b) = match (records_a.next().transpose()?, records_b.next().transpose()?) {
+            (Some(a), Some(b)) => (a, b),
+            (None, None) => break,
+            (a, _) => {
+                let longer = if a.is_some() { "A" } else { "B" };
+                println!("* Runtime {longer} produced more rows than the other one, after {rows} rows");
+                return Ok(1);
+            }
+        };
+        rows += 1;
+        let mut row_differs = false;
+        for ((value_a, value_b), diff) in a.iter().zip(b.iter()).zip(diffs.iter_mut()) {
+            let abs_diff = match (value_a.parse::<f64>(), value_b.parse::<f64>()) {
+                (Ok(x), Ok(y)) if x.is_nan() && y.is_nan() => 0.0,
+                (Ok(x), Ok(y)) => (x - y).abs(),
+                _ if value_a == value_b => 0.0,
+                _ => f64::INFINITY,
+            };
+            if abs_diff > tolerance || abs_diff.is_nan() {
+                diff.differing += 1;
+                row_differs = true;
+            }
+            if abs_diff > diff.max_abs_diff {
+                diff.max_abs_diff = abs_diff;
+            }
+        }
+        if row_differs {
+            differing_rows += 1;
+        }
+    }
+    println!("* Rows compared: {rows}, differing: {differing_rows} (tolerance {tolerance})");
+    for (name, diff) in headers.iter().zip(&diffs) {
+        println!("* '{name}': differing={} max_abs_diff={}", diff.differing, diff.max_abs_diff);
+    }
+    Ok(if differing_rows == 0 { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::compare;
+
+    #[test]
+    fn differences() {
+        let a = b"x,y\n1.0,a\n2.0,b\nNaN,c\n";
+        assert_eq!(0, compare(a, b"x,y\n1.0,a\n2.0000001,b\nNaN,c\n", 1e-6).unwrap());
+        assert_eq!(1, compare(a, b"x,y\n1.0,a\n2.1,b\nNaN,c\n", 1e-6).unwrap());
+        assert_eq!(1, compare(a, b"x,y\n1.0,a\n2.0,B\nNaN,c\n", 1e-6).unwrap());
+        assert_eq!(1, compare(a, b"x,z\n1.0,a\n2.0,b\nNaN,c\n", 1e-6).unwrap());
+        assert_eq!(1, compare(a, b"x,y\n1.0,a\n2.0,b\n", 1e-6).unwrap());
+    }
+}
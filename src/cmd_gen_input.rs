@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::io::Write;
+use daimojo::{MOJO_DataType, RawModel};
+
+/// Range of generated values, for columns not listed explicitly.
+const DEFAULT_RANGE: (f64, f64) = (0.0, 100.0);
+
+/// Generator of random input rows matching features of a model.
+pub struct InputGenerator {
+    pub seed: u64,
+    /// Probability of emitting a missing value, in `0.0..=1.0`
+    pub na_rate: f64,
+    pub default_range: (f64, f64),
+    /// Value ranges of individual columns, by column name
+    pub ranges: HashMap<String, (f64, f64)>,
+}
+
+impl Default for InputGenerator {
+    fn default() -> Self {
+        Self { seed: 0, na_rate: 0.0, default_range: DEFAULT_RANGE, ranges: HashMap::new() }
+    }
+}
+
+impl InputGenerator {
+    /// Write header and `rows` random records as CSV.
+    pub fn write_csv<W: Write>(&self, model: &RawModel, rows: usize, out: W) -> anyhow::Result<W> {
+        let features: Vec<_> = model.features().collect();
+        self.validate(&features).map_err(anyhow::Error::msg)?;
+        let missing_values: Vec<String> = model.missing_values()
+            .map(|s| s.to_string_lossy().to_string())
+            .collect();
+        let mut rng = SplitMix64(self.seed);
+        let mut wtr = csv::Writer::from_writer(out);
+        wtr.write_record(features.iter().map(|(name, _)| name.as_ref()))?;
+        for _ in 0..rows {
+            for (name, data_type) in &features {
+                if self.na_rate > 0.0 && rng.next_f64() < self.na_rate {
+                    let na = match missing_values.len() {
+                        0 => "",
+                        n => &missing_values[rng.next_u64() as usize % n],
+                    };
+                    wtr.write_field(na)?;
+                    continue;
+                }
+                let (min, max) = self.ranges.get(name.as_ref()).copied().unwrap_or(self.default_range);
+                let value = match data_type {
+                    MOJO_DataType::MOJO_BOOL => rng.next_u64().is_multiple_of(2).to_string(),
+                    MOJO_DataType::MOJO_INT32 | MOJO_DataType::MOJO_INT64 => {
+                        // validated to fit the column type, so a full INT64 range still fits i128
+                        let (min, max) = (min.ceil() as i128, max.floor() as i128);
+                        let span = (max - min + 1) as u128;
+                        (min + (rng.next_u64() as u128 % span) as i128).to_string()
+                    }
+                    MOJO_DataType::MOJO_FLOAT | MOJO_DataType::MOJO_DOUBLE => {
+                        let value = min + rng.next_f64() * (max - min);
+                        format!("{}", (value * 1e4).round() / 1e4)
+                    }
+                    MOJO_DataType::MOJO_STRING => {
+                        let span = ((max - min) as u64).saturating_add(1);
+                        format!("s{}", min as i64 as i128 + (rng.next_u64() % span) as i128)
+                    }
+                    MOJO_DataType::MOJO_UNKNOWN => String::new(),
+                };
+                wtr.write_field(value)?;
+            }
+            wtr.write_record(None::<&[u8]>)?;
+        }
+        Ok(wtr.into_inner().map_err(|e| e.into_error())?)
+    }
+
+    /// Check the NA rate, that ranges are given for features only, and that integer columns have at least
+    /// one integer in their range and no value out of their type.
+    fn validate<S: AsRef<str>>(&self, features: &[(S, MOJO_DataType)]) -> Result<(), String> {
+        check_na_rate(self.na_rate)?;
+        if let Some(column) = self.ranges.keys().find(|column| !features.iter().any(|(name, _)| name.as_ref() == column.as_str())) {
+            return Err(format!("range given for unknown column '{column}'"));
+        }
+        for (name, data_type) in features {
+            let name = name.as_ref();
+            let (lowest, highest) = match data_type {
+                MOJO_DataType::MOJO_INT32 => (i32::MIN as i128, i32::MAX as i128),
+                MOJO_DataType::MOJO_INT64 => (i64::MIN as i128, i64::MAX as i128),
+                _ => continue,
+            };
+            let (min, max) = self.ranges.get(name).copied().unwrap_or(self.default_range);
+            if min.ceil() > max.floor() {
+                return Err(format!("range {min}:{max} of integer column '{name}' contains no integer"));
+            }
+            // conversion saturates, beyond the bounds of both types anyway
+            if (min.ceil() as i128) < lowest || max.floor() as i128 > highest {
+                return Err(format!("range {min}:{max} of column '{name}' exceeds values of {data_type:?}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn cmd_gen_input(model: &RawModel, generator: &InputGenerator, rows: usize, output: Option<String>) -> anyhow::Result<u8> {
+    match output {
+        None => {
+            generator.write_csv(model, rows, std::io::stdout().lock())?.flush()?;
+        }
+        Some(output) => {
+            let file = std::io::BufWriter::new(std::fs::File::create(&output)?);
+            generator.write_csv(model, rows, file)?.flush()?;
+            log::info!("Generated {rows} rows into {output}");
+        }
+    }
+    Ok(0)
+}
+
+/// Parse value range in form `MIN:MAX` or `COLUMN=MIN:MAX`.
+pub fn parse_range(s: &str) -> Result<(Option<String>, f64, f64), String> {
+    let (column, range) = match s.rsplit_once('=') {
+        None => (None, s),
+        Some((column, range)) => (Some(column.to_string()), range),
+    };
+    let (min, max) = range.split_once(':')
+        .ok_or_else(|| format!("expected MIN:MAX, found '{range}'"))?;
+    let min: f64 = min.trim().parse().map_err(|e| format!("invalid minimum '{min}': {e}"))?;
+    let max: f64 = max.trim().parse().map_err(|e| format!("invalid maximum '{max}': {e}"))?;
+    if !min.is_finite() || !max.is_finite() {
+        return Err(format!("range bounds must be finite numbers, found '{range}'"));
+    }
+    if min > max {
+        return Err(format!("minimum {min} is greater than maximum {max}"));
+    }
+    Ok((column, min, max))
+}
+
+/// Parse probability of a missing value, between 0 and 1.
+pub fn parse_na_rate(s: &str) -> Result<f64, String> {
+    let rate = s.trim().parse::<f64>().map_err(|e| format!("invalid NA rate '{s}': {e}"))?;
+    check_na_rate(rate)
+}
+
+fn check_na_rate(rate: f64) -> Result<f64, String> {
+    if (0.0..=1.0).contains(&rate) {
+        Ok(rate)
+    } else {
+        Err(format!("NA rate {rate} is not between 0 and 1"))
+    }
+}
+
+/// Small and fast PRNG; the sequence for given seed is stable across versions and platforms.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0.0..1.0`
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use daimojo::{DaiMojoLibrary, MOJO_DataType, RawModel};
+    use daimojo::test_support::{licensed_fake_runtime, ECHO_MODEL};
+    use super::{parse_na_rate, parse_range, InputGenerator, SplitMix64};
+
+    #[test]
+    fn ranges() {
+        assert_eq!(Ok((None, 0.0, 1.5)), parse_range("0:1.5"));
+        assert_eq!(Ok((Some("fixed acidity".to_string()), 4.0, 16.0)), parse_range("fixed acidity=4:16"));
+        assert!(parse_range("4").is_err());
+        assert!(parse_range("5:4").is_err());
+        assert!(parse_range("NaN:4").is_err());
+        assert!(parse_range("0:inf").is_err());
+    }
+
+    #[test]
+    fn na_rates() {
+        assert_eq!(Ok(0.25), parse_na_rate("0.25"));
+        assert_eq!(Ok(1.0), parse_na_rate("1"));
+        assert!(parse_na_rate("1.5").is_err());
+        assert!(parse_na_rate("-0.1").is_err());
+        assert!(parse_na_rate("NaN").is_err());
+    }
+
+    #[test]
+    fn validation() {
+        let features = [("count", MOJO_DataType::MOJO_INT32), ("ratio", MOJO_DataType::MOJO_DOUBLE)];
+        let mut generator = InputGenerator::default();
+        assert!(generator.validate(&features).is_ok());
+        generator.ranges.insert("ratio".to_string(), (0.2, 0.8));
+        assert!(generator.validate(&features).is_ok());
+        generator.ranges.insert("count".to_string(), (0.2, 0.8));
+        assert!(generator.validate(&features).is_err());
+        generator.ranges.insert("count".to_string(), (0.2, 1.0));
+        assert!(generator.validate(&features).is_ok());
+        generator.na_rate = 2.0;
+        assert!(generator.validate(&features).is_err());
+    }
+
+    #[test]
+    fn integer_bounds() {
+        let features = [("small", MOJO_DataType::MOJO_INT32), ("large", MOJO_DataType::MOJO_INT64)];
+        let mut generator = InputGenerator::default();
+        generator.ranges.insert("small".to_string(), (-2147483648.0, 2147483647.0));
+        generator.ranges.insert("large".to_string(), (-9223372036854775808.0, 9.2e18));
+        assert!(generator.validate(&features).is_ok());
+        generator.ranges.insert("small".to_string(), (0.0, 3e9));
+        let error = generator.validate(&features).unwrap_err();
+        assert!(error.contains("'small'"), "{error}");
+        generator.ranges.insert("small".to_string(), (0.0, 1.0));
+        // i64::MAX as f64 is 2^63
+        generator.ranges.insert("large".to_string(), (0.0, i64::MAX as f64));
+        assert!(generator.validate(&features).is_err());
+        generator.ranges.insert("large".to_string(), (-1e19, 0.0));
+        assert!(generator.validate(&features).is_err());
+        generator.ranges.clear();
+        // the default range applies to INT32 columns too
+        generator.default_range = (0.0, 1e10);
+        assert!(generator.validate(&features).is_err());
+
+        let mut generator = InputGenerator::default();
+        generator.ranges.insert("unknown".to_string(), (0.0, 1.0));
+        assert_eq!(Err("range given for unknown column 'unknown'".to_string()), generator.validate(&features));
+    }
+
+    #[test]
+    fn extreme_ranges() {
+        let lib = DaiMojoLibrary::load(licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, ECHO_MODEL, "").unwrap();
+        let mut generator = InputGenerator { seed: 3, ..Default::default() };
+        generator.ranges.insert("int32".to_string(), (-2147483648.0, 2147483647.0));
+        generator.ranges.insert("int64".to_string(), (-9223372036854775808.0, 9.2e18));
+        generator.ranges.insert("string".to_string(), (-1e300, 1e300));
+        let out = generator.write_csv(&model, 100, Vec::new()).unwrap();
+        let mut rdr = csv::Reader::from_reader(out.as_slice());
+        let headers = rdr.headers().unwrap().clone();
+        let column = |name: &str| headers.iter().position(|header| header == name).unwrap();
+        let (int32, int64) = (column("int32"), column("int64"));
+        let records: Vec<csv::StringRecord> = rdr.records().map(Result::unwrap).collect();
+        assert_eq!(100, records.len());
+        let small: Vec<i32> = records.iter().map(|record| record[int32].parse().unwrap()).collect();
+        let large: Vec<i64> = records.iter().map(|record| record[int64].parse().unwrap()).collect();
+        // spread over the whole range, not saturated at one end
+        assert!(small.iter().any(|&i| i < -1_000_000_000) && small.iter().any(|&i| i > 1_000_000_000));
+        assert!(large.iter().any(|&i| i < -1_000_000_000_000_000_000) && large.iter().any(|&i| i > 1_000_000_000_000_000_000));
+    }
+
+    #[test]
+    fn reproducible() {
+        let a: Vec<u64> = { let mut r = SplitMix64(42); (0..5).map(|_| r.next_u64()).collect() };
+        let b: Vec<u64> = { let mut r = SplitMix64(42); (0..5).map(|_| r.next_u64()).collect() };
+        assert_eq!(a, b);
+        let mut r = SplitMix64(7);
+        assert!((0..1000).map(|_| r.next_f64()).all(|f| (0.0..1.0).contains(&f)));
+    }
+}
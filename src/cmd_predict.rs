@@ -1,6 +1,13 @@
-use daimojo::FrameExporter;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use daimojo::{BatchSizer, CsvFormat, FlushPolicy, FrameExporter};
 use daimojo::FrameImporter;
 use daimojo::{RawFrame, RawPipeline};
+use daimojo::compression::{self, Compression};
+use crate::batch_sizing::{self, AdaptiveBatch, InputSample, TARGET_BATCH_TIME};
 
 //TODO missing features:
 // - various CSV in/out flags
@@ -9,47 +16,407 @@ use daimojo::{RawFrame, RawPipeline};
 // - column name mapping + ignore case
 // - headerless csv
 
-/// Minimum size returned by [batch_size_magic].
-const MIN_BATCH_SIZE: usize = 1000;
-
-pub fn cmd_predict(pipeline: &RawPipeline, _output: Option<String>, input: Option<String>, batch_size: usize) -> anyhow::Result<u8> {
-    let batch_size = batch_size_magic(&input, batch_size)?;
-
-    let frame = RawFrame::new(pipeline, batch_size)?;
+/// Pipeline scored in the shadow of the primary one, and the file receiving its output.
+pub struct Shadow<'a> {
+    pub pipeline: &'a RawPipeline<'a>,
+    pub output: String,
+}
 
-    let mut rdr = csv::Reader::from_path(input.unwrap())?;
-    let mut importer = FrameImporter::init(&pipeline, &frame, &mut rdr)?;
-    let mut exporter = FrameExporter::init(&pipeline, &frame)?;
-    // read csv
-    let mut rdr_iter = rdr.records();
-    while let Some(rows) = importer.import_frame(&mut rdr_iter)? {
+/// Input file, or stdin for `None`.
+type Input = Option<PathBuf>;
 
-        // predict
-        pipeline.transform(&frame, rows, false)?;
-        log::debug!("-- batch {rows} rows");
+/// How input is read, scored and written.
+#[derive(Clone)]
+pub struct PredictOptions {
+    /// Rows per batch; for 0, it is determined automatically
+    pub batch_size: usize,
+    pub format: CsvFormat,
+    pub flush_policy: FlushPolicy,
+    /// Overlap parsing, scoring and writing on separate threads
+    pub pipelined: bool,
+    /// Limit of frame memory, when the batch size is determined automatically
+    pub max_memory: u64,
+    /// Compression of input; detected when not given
+    pub compression: Option<Compression>,
+    /// Compression of output; by the file extension when not given
+    pub out_compression: Option<Compression>,
+}
 
-        // output csv
-        exporter.export_frame(rows)?;
+pub fn cmd_predict(pipeline: &RawPipeline, output: Option<String>, out_template: Option<String>, inputs: &[String], options: PredictOptions, shadow: Option<Shadow>) -> anyhow::Result<u8> {
+    let inputs = expand_inputs(inputs)?;
+    for input in inputs.iter().flatten() {
+        check_header(pipeline, input, options.compression)?;
     }
-    log::info!("Total rows: {}", exporter.saved_rows);
+    // `-` stands for stdout
+    let output = output.filter(|path| path != "-").map(PathBuf::from);
+    let rows = match out_template {
+        None => predict(pipeline, &inputs, output.as_deref(), &options, shadow)?,
+        Some(template) => {
+            if shadow.is_some() {
+                anyhow::bail!("Shadow scoring writes a single output, it cannot be combined with an output template");
+            }
+            let outputs = output_names(&template, &inputs)?;
+            let mut rows = 0;
+            for (input, output) in inputs.iter().zip(&outputs) {
+                log::info!("Scoring {} into {}", input_name(input), output.display());
+                rows += predict(pipeline, std::slice::from_ref(input), Some(output), &options, None)?;
+            }
+            rows
+        }
+    };
+    log::info!("Total rows: {rows}");
     //
     Ok(0)
 }
 
-/// Heuristics to estimate best batch size possible for given input.
-/// The goal is, try to hold everything in memory, but don't request too much of it.
-fn batch_size_magic(input: &Option<String>, batch_size: usize) -> std::io::Result<usize> {
-    Ok(match (batch_size, &input) {
-        (0, None) => MIN_BATCH_SIZE * 10,
-        (0, Some(path)) => {
-            let input_len = std::fs::metadata(path)?.len();
-            let mut batch_size = input_len / 50;
-            if batch_size < 1000 {
-                batch_size = 1000;
+/// Score inputs into one output; several inputs are concatenated, under one header.
+pub fn predict(pipeline: &RawPipeline, inputs: &[Input], output: Option<&Path>, options: &PredictOptions, shadow: Option<Shadow>) -> anyhow::Result<usize> {
+    if let Some(output) = output {
+        if let Some(input) = same_file(output, inputs.iter().flatten().map(PathBuf::as_path))? {
+            anyhow::bail!("Output {} would overwrite input {}", output.display(), input.display());
+        }
+    }
+    if let Some(shadow) = &shadow {
+        check_shadow_output(Path::new(&shadow.output), output, inputs)?;
+    }
+    let adaptive = options.batch_size == 0;
+    let batch_size = match options.batch_size {
+        0 => inputs.iter()
+            .map(|input| auto_batch_size(pipeline, input.as_deref(), options.compression, options.max_memory))
+            .collect::<anyhow::Result<Vec<usize>>>()?
+            .into_iter().max().unwrap_or(1),
+        batch_size => batch_size,
+    };
+    let (sink, out_compression): (Box<dyn Write + Send>, _) = match output {
+        None => (Box::new(std::io::stdout()), options.out_compression.unwrap_or_default()),
+        Some(output) => (Box::new(File::create(output)?), options.out_compression.unwrap_or_else(|| Compression::from_extension(output))),
+    };
+    let mut writer = out_compression.writer(sink)?;
+    // an automatic size is the largest batch; actual batches start smaller, so that pipelined batches overlap
+    let mut batch_size: Box<dyn BatchSizer> = if adaptive {
+        Box::new(AdaptiveBatch::new(batch_size, TARGET_BATCH_TIME))
+    } else {
+        Box::new(batch_size)
+    };
+    let batch_size = &mut *batch_size;
+    let rows = match (inputs, shadow) {
+        ([input], Some(shadow)) => {
+            let mut rdr = open_input(input, options.compression)?;
+            cmd_predict_shadow(pipeline, &mut rdr, batch_size, options, &mut writer, shadow)?
+        }
+        (_, Some(_)) => anyhow::bail!("Shadow scoring takes a single input"),
+        ([input], None) if options.pipelined => {
+            let rdr = open_input(input, options.compression)?;
+            daimojo::pipelined::score_pipelined(pipeline, rdr, &mut writer, batch_size, &options.format, options.flush_policy)?
+        }
+        (_, None) if options.pipelined => anyhow::bail!("Pipelined scoring of several inputs needs one output per input, see --out-template"),
+        (_, None) => score(pipeline, inputs, &mut writer, batch_size, options)?,
+    };
+    writer.finish()?;
+    Ok(rows)
+}
+
+/// Score batches one after another, input after input, with one frame; returns the number of rows.
+fn score<W: Write>(pipeline: &RawPipeline, inputs: &[Input], writer: W, mut batch_size: impl BatchSizer, options: &PredictOptions) -> anyhow::Result<usize> {
+    let frame = RawFrame::new(pipeline, batch_size.capacity())?;
+    let mut exporter = FrameExporter::with_writer(pipeline, &frame, writer)?
+        .with_bool_format(options.format.bools.clone())
+        .with_number_format(options.format.numbers.clone())
+        .with_flush_policy(options.flush_policy);
+    for input in inputs {
+        let mut rdr = open_input(input, options.compression)?;
+        let mut importer = FrameImporter::init(pipeline, &frame, &mut rdr)?
+            .with_bool_format(options.format.bools.clone());
+        importer.set_batch_size(batch_size.current());
+        // read csv
+        while let Some(rows) = importer.import_batch(&mut rdr)? {
+
+            // predict
+            let start = Instant::now();
+            pipeline.transform(&frame, rows, false)?;
+            log::debug!("-- batch {rows} rows");
+            importer.set_batch_size(batch_size.update(rows, start.elapsed()));
+
+            // output csv
+            exporter.export_frame(rows)?;
+        }
+    }
+    exporter.flush()?;
+    Ok(exporter.saved_rows)
+}
+
+fn open_input(input: &Input, compression: Option<Compression>) -> std::io::Result<csv::Reader<Box<dyn Read + Send>>> {
+    Ok(csv::Reader::from_reader(compression::open_input(input.as_deref(), compression)?))
+}
+
+fn input_name(input: &Input) -> Cow<'_, str> {
+    match input {
+        None => Cow::Borrowed("stdin"),
+        Some(path) => path.to_string_lossy(),
+    }
+}
+
+/// Expand glob patterns, each in sorted order; `-` and no inputs at all stand for stdin.
+fn expand_inputs(args: &[String]) -> anyhow::Result<Vec<Input>> {
+    if args.is_empty() {
+        return Ok(vec![None]);
+    }
+    let mut inputs = Vec::new();
+    for arg in args {
+        if arg == "-" {
+            inputs.push(None);
+        } else if !arg.contains(['*', '?', '[']) {
+            inputs.push(Some(PathBuf::from(arg)));
+        } else {
+            let matches = glob::glob(arg)?.collect::<Result<Vec<PathBuf>, _>>()?;
+            if matches.is_empty() {
+                anyhow::bail!("No input matches '{arg}'");
+            }
+            inputs.extend(matches.into_iter().map(Some));
+        }
+    }
+    if inputs.iter().filter(|input| input.is_none()).count() > 1 {
+        anyhow::bail!("Stdin can be used as input only once");
+    }
+    Ok(inputs)
+}
+
+/// Fail on input without all model features before anything is scored.
+pub fn check_header(pipeline: &RawPipeline, path: &Path, compression: Option<Compression>) -> anyhow::Result<()> {
+    let mut rdr = csv::Reader::from_reader(compression::open_input(Some(path), compression)?);
+    let headers = rdr.byte_headers()?;
+    let missing: Vec<Cow<str>> = pipeline.model.feature_names_iter()
+        .filter(|name| !headers.iter().any(|header| header == name.to_bytes()))
+        .map(|name| name.to_string_lossy())
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!("Input {} lacks features: {}", path.display(), missing.join(", "));
+    }
+    Ok(())
+}
+
+/// Output path for each input, from a template with placeholders:
+/// `{dir}` directory of the input, `{name}` its file name, `{stem}` the file name without `.gz`/`.zst` and `.csv`
+/// extensions, and `{index}` position of the input, from 0. Stdin has name and stem `stdin`, in directory `.`.
+fn output_names(template: &str, inputs: &[Input]) -> anyhow::Result<Vec<PathBuf>> {
+    // with their resolved paths
+    let mut outputs: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        let (dir, name) = match input {
+            None => (Cow::Borrowed("."), Cow::Borrowed("stdin")),
+            Some(path) => {
+                let dir = match path.parent() {
+                    Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy(),
+                    _ => Cow::Borrowed("."),
+                };
+                (dir, path.file_name().map_or(Cow::Borrowed(""), |name| name.to_string_lossy()))
             }
-            log::warn!("Batch size was automatically set to {batch_size}");
-            batch_size as usize
+        };
+        let stem = [".gz", ".zst"].iter().fold(name.as_ref(), |stem, ext| stem.strip_suffix(ext).unwrap_or(stem));
+        let stem = stem.strip_suffix(".csv").unwrap_or(stem);
+        let output = PathBuf::from(template
+            .replace("{dir}", &dir)
+            .replace("{name}", &name)
+            .replace("{stem}", stem)
+            .replace("{index}", &index.to_string()));
+        if same_file(&output, inputs.iter().flatten().map(PathBuf::as_path))?.is_some() {
+            anyhow::bail!("Output template '{template}' would overwrite input {}", output.display());
         }
-        (batch_size, _) => batch_size,
-    })
+        let resolved = resolve_path(&output)?;
+        if outputs.iter().any(|(_, other)| *other == resolved) {
+            anyhow::bail!("Output template '{template}' gives {} for more inputs", output.display());
+        }
+        outputs.push((output, resolved));
+    }
+    Ok(outputs.into_iter().map(|(output, _)| output).collect())
+}
+
+/// Absolute path with symbolic links resolved, so that different spellings of one file compare equal.
+/// For a file that does not exist yet, its directory is resolved instead, or nothing when that does not exist either.
+fn resolve_path(path: &Path) -> std::io::Result<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Ok(path);
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    match (dir.canonicalize(), path.file_name()) {
+        (Ok(dir), Some(name)) => Ok(dir.join(name)),
+        _ => std::path::absolute(path),
+    }
+}
+
+/// The first of `others` that is the same file as `path`, whatever the spelling of their paths.
+fn same_file<'p>(path: &Path, others: impl IntoIterator<Item = &'p Path>) -> std::io::Result<Option<&'p Path>> {
+    let resolved = resolve_path(path)?;
+    for other in others {
+        if resolve_path(other)? == resolved {
+            return Ok(Some(other));
+        }
+    }
+    Ok(None)
+}
+
+/// The shadow output and its statistics must not replace the primary output, any input, or each other.
+fn check_shadow_output(shadow: &Path, output: Option<&Path>, inputs: &[Input]) -> anyhow::Result<()> {
+    let stats = shadow_stats(shadow);
+    if same_file(shadow, [stats.as_path()])?.is_some() {
+        anyhow::bail!("Shadow output {} is the file for its statistics; use another extension than .json", shadow.display());
+    }
+    for shadow_file in [shadow, &stats] {
+        if let Some(other) = same_file(shadow_file, output.into_iter().chain(inputs.iter().flatten().map(PathBuf::as_path)))? {
+            anyhow::bail!("Shadow output {} or its statistics would overwrite {}", shadow.display(), other.display());
+        }
+    }
+    Ok(())
+}
+
+/// File with divergence statistics of the shadow output.
+fn shadow_stats(shadow: &Path) -> PathBuf {
+    shadow.with_extension("json")
+}
+
+/// Write the primary output as usual, the candidate output into the shadow file,
+/// and divergence statistics as JSON next to it.
+fn cmd_predict_shadow(pipeline: &RawPipeline, rdr: &mut csv::Reader<impl Read>, batch_size: impl BatchSizer, options: &PredictOptions, writer: impl Write, shadow: Shadow) -> anyhow::Result<usize> {
+    let shadow_path = Path::new(&shadow.output);
+    let mut shadow_out = Compression::from_extension(shadow_path).writer(File::create(shadow_path)?)?;
+    let report = daimojo::shadow::shadow_score(pipeline, shadow.pipeline, rdr, batch_size, &options.format, writer, &mut shadow_out, options.flush_policy)?;
+    shadow_out.finish()?;
+    let stats = shadow_stats(shadow_path);
+    serde_json::to_writer_pretty(File::create(&stats)?, &report)?;
+    for (name, divergence) in &report.columns {
+        log::info!("Shadow '{name}': differing={} max_abs_diff={}", divergence.differing, divergence.max_abs_diff);
+    }
+    if !report.primary_only.is_empty() || !report.candidate_only.is_empty() {
+        log::warn!("Shadow outputs differ; primary only: [{}], candidate only: [{}]",
+                   report.primary_only.join(", "), report.candidate_only.join(", "));
+    }
+    log::info!("Shadow output written to {}, statistics to {}", shadow.output, stats.display());
+    Ok(report.rows)
+}
+
+/// Estimate batch size from a sample of the input, so that the frame takes at most `max_memory`.
+/// Compressed input is sampled after decompression, and its size is extrapolated.
+fn auto_batch_size(pipeline: &RawPipeline, input: Option<&Path>, compression: Option<Compression>, max_memory: u64) -> anyhow::Result<usize> {
+    let (input_len, sample) = match input {
+        // stdin cannot be sampled without consuming it
+        None => (None, InputSample::default()),
+        Some(path) => (
+            Some(compression::estimate_uncompressed_len(path, compression)?),
+            InputSample::read(pipeline, compression::open_input(Some(path), compression)?)?,
+        ),
+    };
+    let row_memory = batch_sizing::row_memory(pipeline, &sample);
+    let batch_size = batch_sizing::estimate(input_len, &sample, row_memory, max_memory);
+    log::info!("Batch size was automatically set to {batch_size}, with {row_memory} bytes per row estimated from {} sampled rows", sample.rows);
+    Ok(batch_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use daimojo::{DaiMojoLibrary, FlushPolicy, MOJO_Transform_Ops, RawModel, RawPipeline};
+    use crate::batch_sizing::INITIAL_BATCH_SIZE;
+    use daimojo::test_support::{licensed_fake_runtime, TempDir};
+    use super::{check_shadow_output, expand_inputs, output_names, predict, same_file, PredictOptions};
+
+    #[test]
+    fn inputs() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+        assert_eq!(vec![None], expand_inputs(&[]).unwrap());
+        assert_eq!(
+            vec![Some(PathBuf::from("data/iris/pipeline.mojo")), Some(PathBuf::from("data/wine/pipeline.mojo")), None],
+            expand_inputs(&args(&["data/[iw]*/pipeline.mojo", "-"])).unwrap());
+        assert!(expand_inputs(&args(&["data/*/nothing.csv"])).is_err());
+        assert!(expand_inputs(&args(&["-", "-"])).is_err());
+    }
+
+    #[test]
+    fn outputs() {
+        let inputs = vec![Some(PathBuf::from("in/a.csv.gz")), Some(PathBuf::from("b.csv")), None];
+        assert_eq!(
+            vec![PathBuf::from("in/a.pred.csv"), PathBuf::from("./b.pred.csv"), PathBuf::from("./stdin.pred.csv")],
+            output_names("{dir}/{stem}.pred.csv", &inputs).unwrap());
+        assert_eq!(
+            vec![PathBuf::from("out/0-a.csv.gz"), PathBuf::from("out/1-b.csv"), PathBuf::from("out/2-stdin")],
+            output_names("out/{index}-{name}", &inputs).unwrap());
+        assert!(output_names("out.csv", &inputs).is_err());
+        assert!(output_names("{dir}/{name}", &inputs).is_err());
+        // the same file, spelled differently
+        assert!(output_names("{dir}/{name}", &[Some(PathBuf::from("b.csv"))]).is_err());
+        assert!(output_names("./data/../{name}", &[Some(PathBuf::from("Cargo.toml"))]).is_err());
+        assert!(output_names("{dir}/{stem}.pred.csv", &[Some(PathBuf::from("a.csv")), Some(PathBuf::from("src/../a.csv.gz"))]).is_err());
+    }
+
+    #[test]
+    fn same_files() {
+        assert_eq!(Some(Path::new("./Cargo.toml")), same_file(Path::new("src/../Cargo.toml"), [Path::new("a.csv"), Path::new("./Cargo.toml")]).unwrap());
+        assert_eq!(None, same_file(Path::new("out.csv"), [Path::new("Cargo.toml")]).unwrap());
+    }
+
+    #[test]
+    fn output_overwriting_input() {
+        let lib = DaiMojoLibrary::load(licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, "data/iris/pipeline.mojo", "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let dir = TempDir::new("output_overwriting_input");
+        let input = dir.path().join("data.csv");
+        std::fs::write(&input, "x\n1\n").unwrap();
+        let options = PredictOptions {
+            batch_size: 10,
+            format: Default::default(),
+            flush_policy: FlushPolicy::Batch,
+            pipelined: false,
+            max_memory: 1 << 30,
+            compression: None,
+            out_compression: None,
+        };
+        let inputs = [Some(PathBuf::from("Cargo.toml")), Some(input.clone())];
+        let output = dir.path().join(".").join("data.csv");
+        let error = predict(&pipeline, &inputs, Some(&output), &options, None).unwrap_err();
+        assert!(error.to_string().contains("would overwrite input"), "{error}");
+        assert_eq!("x\n1\n", std::fs::read_to_string(&input).unwrap(), "input is intact");
+    }
+
+    #[test]
+    fn shadow_outputs() {
+        let inputs = vec![Some(PathBuf::from("Cargo.toml")), None];
+        assert!(check_shadow_output(Path::new("shadow.csv"), Some(Path::new("out.csv")), &inputs).is_ok());
+        assert!(check_shadow_output(Path::new("shadow.csv"), None, &inputs).is_ok());
+        assert!(check_shadow_output(Path::new("out.csv"), Some(Path::new("./out.csv")), &inputs).is_err());
+        assert!(check_shadow_output(Path::new("out.csv"), Some(Path::new("out.json")), &inputs).is_err());
+        assert!(check_shadow_output(Path::new("src/../Cargo.toml"), None, &inputs).is_err());
+        assert!(check_shadow_output(Path::new("shadow.json"), None, &inputs).is_err());
+    }
+
+    #[test]
+    fn pipelined_with_automatic_batch_size() {
+        let lib = DaiMojoLibrary::load(licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, "data/iris/pipeline.mojo", "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let dir = TempDir::new("pipelined_with_automatic_batch_size");
+        let input = dir.path().join("input.csv");
+        let rows = 2 * INITIAL_BATCH_SIZE + 500;
+        let data: String = std::iter::once("x\n".to_string()).chain((0..rows).map(|i| format!("{i}\n"))).collect();
+        std::fs::write(&input, data).unwrap();
+
+        let mut options = PredictOptions {
+            batch_size: 0,
+            format: Default::default(),
+            flush_policy: FlushPolicy::Batch,
+            pipelined: false,
+            max_memory: 1 << 30,
+            compression: None,
+            out_compression: None,
+        };
+        let inputs = [Some(input)];
+        let sequential = dir.path().join("sequential.csv");
+        assert_eq!(rows, predict(&pipeline, &inputs, Some(&sequential), &options, None).unwrap());
+        options.pipelined = true;
+        let pipelined = dir.path().join("pipelined.csv");
+        assert_eq!(rows, predict(&pipeline, &inputs, Some(&pipelined), &options, None).unwrap());
+        assert_eq!(std::fs::read(sequential).unwrap(), std::fs::read(pipelined).unwrap());
+    }
 }
@@ -0,0 +1,316 @@
+//! Drop-folder scoring: CSV files appearing in the input directory are scored with the already loaded pipeline
+//!
+//! A file is picked up once its size and modification time stay the same over two polls, so that files
+//! still being copied in are left alone. Output is written under a temporary name and renamed when complete.
+//! The input then moves to the done or failed directory, next to a JSON status file.
+//! Names already taken in the output, done or failed directory get a counter, like `NAME-1.csv`, so nothing is overwritten.
+//! Errors with one file are logged and the file is skipped, until it changes; the watcher keeps going.
+
+use std::collections::HashMap;
+use std::fs;
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use chrono::Utc;
+use serde::Serialize;
+use daimojo::compression::Compression;
+use daimojo::RawPipeline;
+use crate::cmd_predict::{self, PredictOptions};
+
+/// Directories of the drop folder.
+pub struct WatchDirs {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub done: PathBuf,
+    pub failed: PathBuf,
+}
+
+/// Written as `NAME.status.json` next to the processed input.
+#[derive(Debug, Serialize)]
+struct Status {
+    input: String,
+    /// Present when scoring succeeded
+    output: Option<String>,
+    rows: usize,
+    error: Option<String>,
+    started: String,
+    seconds: f64,
+}
+
+/// Size and modification time, to tell whether a file is still being written.
+type FileState = (u64, SystemTime);
+
+/// Score files as they appear, forever; with `once`, score the files present now and return 1 if any could not be moved away.
+pub fn cmd_watch(pipeline: &RawPipeline, dirs: &WatchDirs, options: &PredictOptions, poll_interval: Duration, once: bool) -> anyhow::Result<u8> {
+    check_dirs(dirs)?;
+    log::info!("Watching {} for CSV files", dirs.input.display());
+    let mut pending = HashMap::new();
+    // files that could not be moved away, with their state then
+    let mut stuck: HashMap<PathBuf, FileState> = HashMap::new();
+    loop {
+        let found = match candidates(&dirs.input) {
+            Ok(found) => found,
+            Err(e) if once => return Err(e).with_context(|| format!("Cannot list {}", dirs.input.display())),
+            Err(e) => {
+                log::error!("Cannot list {}: {e}", dirs.input.display());
+                HashMap::new()
+            }
+        };
+        stuck.retain(|path, state| found.get(path) == Some(state));
+        let mut ready: Vec<PathBuf> = if once {
+            found.into_keys().collect()
+        } else {
+            settled(&mut pending, found)
+        };
+        ready.retain(|path| !stuck.contains_key(path));
+        ready.sort();
+        for input in ready {
+            if let Err(e) = process(pipeline, dirs, options, &input) {
+                log::error!("Skipping {} until it changes: {e:#}", input.display());
+                if let Ok(state) = file_state(&input) {
+                    stuck.insert(input, state);
+                }
+            }
+        }
+        if once {
+            return Ok(if stuck.is_empty() { 0 } else { 1 });
+        }
+        std::thread::sleep(poll_interval);
+    }
+}
+
+/// Create the directories, which must all be different, as files are moved between them.
+fn check_dirs(dirs: &WatchDirs) -> anyhow::Result<()> {
+    let named = [("input", &dirs.input), ("output", &dirs.output), ("done", &dirs.done), ("failed", &dirs.failed)];
+    let mut resolved: Vec<(&str, PathBuf)> = Vec::new();
+    for (kind, dir) in named {
+        fs::create_dir_all(dir).with_context(|| format!("Cannot create {kind} directory {}", dir.display()))?;
+        let path = dir.canonicalize()?;
+        if let Some((other, _)) = resolved.iter().find(|(_, other)| *other == path) {
+            anyhow::bail!("The {kind} directory {} is also the {other} directory", dir.display());
+        }
+        resolved.push((kind, path));
+    }
+    Ok(())
+}
+
+/// CSV files in the directory, possibly compressed; hidden files, and files that cannot be inspected, are skipped.
+fn candidates(dir: &Path) -> std::io::Result<HashMap<PathBuf, FileState>> {
+    let mut found = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("Cannot read an entry of {}: {e}", dir.display());
+                continue;
+            }
+        };
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || csv_extension(&name).is_none() {
+            continue;
+        }
+        // the file may be gone meanwhile
+        match file_state(&entry.path()) {
+            Ok(state) => {
+                found.insert(entry.path(), state);
+            }
+            Err(e) => log::warn!("Skipping {}: {e}", entry.path().display()),
+        }
+    }
+    Ok(found)
+}
+
+/// Extension of a CSV file, possibly compressed.
+fn csv_extension(name: &str) -> Option<&'static str> {
+    [".csv.gz", ".csv.zst", ".csv"].into_iter().find(|ext| name.ends_with(ext))
+}
+
+/// State of a regular file; other files are an error.
+fn file_state(path: &Path) -> std::io::Result<FileState> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Err(std::io::Error::other("not a regular file"));
+    }
+    Ok((metadata.len(), metadata.modified()?))
+}
+
+/// Files found unchanged since the previous poll; `pending` keeps the state of this poll for the next one.
+fn settled(pending: &mut HashMap<PathBuf, FileState>, found: HashMap<PathBuf, FileState>) -> Vec<PathBuf> {
+    let ready = found.iter()
+        .filter(|(path, state)| pending.get(*path) == Some(state))
+        .map(|(path, _)| path.clone())
+        .collect();
+    *pending = found;
+    ready
+}
+
+/// Score one input, then move it away with its status. Scoring errors go into the status;
+/// errors are returned only when the input cannot be moved, as it would be picked up again.
+fn process(pipeline: &RawPipeline, dirs: &WatchDirs, options: &PredictOptions, input: &Path) -> anyhow::Result<()> {
+    let name = input.file_name().expect("listed file has a name").to_string_lossy();
+    let name = free_name(dirs, &name);
+    let output = dirs.output.join(&name);
+    let started = Utc::now();
+    let start = Instant::now();
+    log::info!("Scoring {}", input.display());
+    let result = score_file(pipeline, options, input, &output);
+    let seconds = start.elapsed().as_secs_f64();
+    let (target, status) = match result {
+        Ok(rows) => {
+            log::info!("Scored {rows} rows of {} into {}", input.display(), output.display());
+            (&dirs.done, Status { output: Some(output.display().to_string()), rows, error: None, ..status(input, started, seconds) })
+        }
+        Err(e) => {
+            log::error!("Scoring {} failed: {e:#}", input.display());
+            (&dirs.failed, Status { error: Some(format!("{e:#}")), ..status(input, started, seconds) })
+        }
+    };
+    fs::rename(input, target.join(&name))?;
+    write_atomically(&target.join(format!("{name}.status.json")), &serde_json::to_vec_pretty(&status)?)?;
+    Ok(())
+}
+
+/// The name, or the first of `STEM-1.EXT`, `STEM-2.EXT`, ... not taken by an output, a processed input or a status.
+fn free_name(dirs: &WatchDirs, name: &str) -> String {
+    let ext = csv_extension(name).unwrap_or("");
+    let stem = &name[..name.len() - ext.len()];
+    (0..)
+        .map(|counter| match counter {
+            0 => name.to_string(),
+            counter => format!("{stem}-{counter}{ext}"),
+        })
+        .find(|name| {
+            let status = format!("{name}.status.json");
+            !dirs.output.join(name).exists()
+                && [&dirs.done, &dirs.failed].iter().all(|dir| !dir.join(name).exists() && !dir.join(&status).exists())
+        })
+        .expect("some counter is free")
+}
+
+fn status(input: &Path, started: chrono::DateTime<Utc>, seconds: f64) -> Status {
+    Status { input: input.display().to_string(), output: None, rows: 0, error: None, started: started.to_rfc3339(), seconds }
+}
+
+/// Score into a temporary file in the output directory, renamed to `output` when complete.
+fn score_file(pipeline: &RawPipeline, options: &PredictOptions, input: &Path, output: &Path) -> anyhow::Result<usize> {
+    cmd_predict::check_header(pipeline, input, options.compression)?;
+    let partial = partial_path(output);
+    // the temporary name has no meaningful extension
+    let options = PredictOptions {
+        out_compression: options.out_compression.or(Some(Compression::from_extension(output))),
+        ..options.clone()
+    };
+    let inputs = [Some(input.to_path_buf())];
+    match cmd_predict::predict(pipeline, &inputs, Some(&partial), &options, None) {
+        Ok(rows) => {
+            fs::rename(&partial, output)?;
+            Ok(rows)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let partial = partial_path(path);
+    fs::write(&partial, contents)?;
+    fs::rename(&partial, path)
+}
+
+/// Hidden name in the same directory, so that the final rename does not cross file systems.
+fn partial_path(path: &Path) -> PathBuf {
+    let name = path.file_name().expect("path of a file").to_string_lossy();
+    path.with_file_name(format!(".{name}.part"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+    use daimojo::{DaiMojoLibrary, FlushPolicy, MOJO_Transform_Ops, RawModel, RawPipeline};
+    use crate::cmd_predict::PredictOptions;
+    use daimojo::test_support::{licensed_fake_runtime, TempDir, ECHO_MODEL};
+    use super::{check_dirs, cmd_watch, settled, WatchDirs};
+
+    fn dirs(root: &Path) -> WatchDirs {
+        WatchDirs { input: root.join("in"), output: root.join("out"), done: root.join("done"), failed: root.join("failed") }
+    }
+
+    #[test]
+    fn settling() {
+        let time = SystemTime::UNIX_EPOCH;
+        let mut pending = HashMap::new();
+        let poll = |files: &[(&str, u64)]| files.iter().map(|&(name, len)| (PathBuf::from(name), (len, time))).collect();
+        assert!(settled(&mut pending, poll(&[("a.csv", 10)])).is_empty());
+        assert_eq!(vec![PathBuf::from("a.csv")], settled(&mut pending, poll(&[("a.csv", 10), ("b.csv", 5)])));
+        // still growing
+        assert!(settled(&mut pending, poll(&[("b.csv", 7)])).is_empty());
+    }
+
+    #[test]
+    fn drop_folder() {
+        let lib = DaiMojoLibrary::load(licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, ECHO_MODEL, "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let header = "bool,int32,int64,float,double,string\n";
+
+        let root = TempDir::new("drop_folder");
+        let dirs = dirs(root.path());
+        std::fs::create_dir_all(&dirs.input).unwrap();
+        std::fs::write(dirs.input.join("good.csv"), format!("{header}true,1,10,1.5,2.5,a\nNA,,,,,\nfalse,3,30,3.5,4.5,c\n")).unwrap();
+        // gzip magic, but no valid stream
+        std::fs::write(dirs.input.join("broken.csv.gz"), b"\x1f\x8b\x08\x00garbage").unwrap();
+        std::fs::write(dirs.input.join("notes.txt"), "ignored").unwrap();
+
+        let options = PredictOptions {
+            batch_size: 2,
+            format: Default::default(),
+            flush_policy: FlushPolicy::Batch,
+            pipelined: false,
+            max_memory: 1 << 20,
+            compression: None,
+            out_compression: None,
+        };
+        assert_eq!(0, cmd_watch(&pipeline, &dirs, &options, Duration::ZERO, true).unwrap());
+
+        assert_eq!("bool_out,int32_out,int64_out,float_out,double_out,string_out\n\
+            true,1,10,1.5,2.5,a\n\
+            false,2147483647,9223372036854775807,NaN,NaN,\n\
+            false,3,30,3.5,4.5,c\n", std::fs::read_to_string(dirs.output.join("good.csv")).unwrap());
+        assert!(dirs.done.join("good.csv").is_file());
+        let status: serde_json::Value = serde_json::from_slice(&std::fs::read(dirs.done.join("good.csv.status.json")).unwrap()).unwrap();
+        assert_eq!(3, status["rows"]);
+        assert!(status["error"].is_null());
+
+        assert!(!dirs.output.join("broken.csv.gz").exists());
+        assert!(dirs.failed.join("broken.csv.gz").is_file());
+        let status: serde_json::Value = serde_json::from_slice(&std::fs::read(dirs.failed.join("broken.csv.gz.status.json")).unwrap()).unwrap();
+        assert!(status["error"].is_string());
+
+        assert!(dirs.input.join("notes.txt").is_file());
+        assert_eq!(1, std::fs::read_dir(&dirs.output).unwrap().count());
+
+        // same name again: nothing is overwritten
+        std::fs::write(dirs.input.join("good.csv"), format!("{header}true,5,50,5.5,6.5,e\n")).unwrap();
+        assert_eq!(0, cmd_watch(&pipeline, &dirs, &options, Duration::ZERO, true).unwrap());
+        assert_eq!(3, std::fs::read_to_string(dirs.output.join("good.csv")).unwrap().lines().count() - 1);
+        assert!(std::fs::read_to_string(dirs.output.join("good-1.csv")).unwrap().ends_with("\ntrue,5,50,5.5,6.5,e\n"));
+        assert!(dirs.done.join("good-1.csv").is_file());
+        let status: serde_json::Value = serde_json::from_slice(&std::fs::read(dirs.done.join("good-1.csv.status.json")).unwrap()).unwrap();
+        assert_eq!(1, status["rows"]);
+    }
+
+    #[test]
+    fn distinct_dirs() {
+        let root = TempDir::new("distinct_dirs");
+        assert!(check_dirs(&dirs(root.path())).is_ok());
+        let same_output = WatchDirs { output: root.path().join("out/../in"), ..dirs(root.path()) };
+        assert!(check_dirs(&same_output).is_err());
+        let same_failed = WatchDirs { failed: root.path().join("done"), ..dirs(root.path()) };
+        assert!(check_dirs(&same_failed).is_err());
+    }
+}
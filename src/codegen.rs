@@ -0,0 +1,182 @@
+//! Generator of typed Rust code for a pipeline
+//!
+//! The generated code contains `Input` and `Output` structs matching the pipeline schema,
+//! and a `PipelineScorer` owning a [crate::Scorer], with a typed `score` method.
+//! It can be produced by `daimojo codegen`, or from a build script:
+//!
+//! ```no_run
+//! # fn main() -> daimojo::Result<()> {
+//! use daimojo::{DaiMojoLibrary, MOJO_Transform_Ops, RawModel, RawPipeline};
+//! let lib = DaiMojoLibrary::load("libdaimojo.so")?;
+//! let model = RawModel::load(&lib, "pipeline.mojo", "")?;
+//! let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT)?;
+//! daimojo::codegen::write_file(&pipeline, "src/pipeline_types.rs")?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+use crate::daimojo_library::{MOJO_DataType, RawPipeline};
+use crate::{error, MojoError};
+
+/// Generate code for given pipeline into a file.
+pub fn write_file<P: AsRef<Path>>(pipeline: &RawPipeline, path: P) -> error::Result<()> {
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    generate(pipeline, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Generate code for given pipeline into the writer.
+pub fn generate<W: Write>(pipeline: &RawPipeline, out: &mut W) -> error::Result<()> {
+    let model = pipeline.model;
+    let features: Vec<(String, MOJO_DataType)> = model.features()
+        .map(|(name, data_type)| (name.into_owned(), data_type))
+        .collect();
+    let outputs: Vec<(String, MOJO_DataType)> = pipeline.outputs()
+        .map(|(name, data_type)| (name.into_owned(), data_type))
+        .collect();
+    render(&model.uuid().to_string_lossy(), &features, &outputs, out)
+}
+
+fn render<W: Write>(uuid: &str, features: &[(String, MOJO_DataType)], outputs: &[(String, MOJO_DataType)], out: &mut W) -> error::Result<()> {
+    writeln!(out, "// Generated by daimojo codegen from pipeline {uuid} - do not edit.")?;
+    writeln!(out)?;
+    writeln!(out, "#[derive(Clone, Debug, Default, serde::Serialize)]")?;
+    writeln!(out, "pub struct Input {{")?;
+    render_fields(features, true, out)?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "#[derive(Clone, Debug, serde::Deserialize)]")?;
+    writeln!(out, "pub struct Output {{")?;
+    render_fields(outputs, false, out)?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "/// Scorer of the pipeline {uuid}, with typed rows.")?;
+    writeln!(out, "pub struct PipelineScorer<'a> {{")?;
+    writeln!(out, "    scorer: daimojo::Scorer<'a>,")?;
+    writeln!(out, "}}")?;
+    writeln!(out)?;
+    writeln!(out, "impl<'a> PipelineScorer<'a> {{")?;
+    writeln!(out, "    pub fn new(pipeline: &'a daimojo::RawPipeline<'a>) -> Self {{")?;
+    writeln!(out, "        Self::with_scorer(daimojo::Scorer::new(pipeline))")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(out, "    /// Use a configured scorer, like one with a frame pool.")?;
+    writeln!(out, "    pub fn with_scorer(scorer: daimojo::Scorer<'a>) -> Self {{")?;
+    writeln!(out, "        Self {{ scorer }}")?;
+    writeln!(out, "    }}")?;
+    writeln!(out)?;
+    writeln!(out, "    pub fn score(&self, rows: &[Input]) -> daimojo::Result<Vec<Output>> {{")?;
+    writeln!(out, "        self.scorer.score(rows)")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")?;
+    Ok(())
+}
+
+fn render_fields<W: Write>(columns: &[(String, MOJO_DataType)], input: bool, out: &mut W) -> error::Result<()> {
+    let mut used = HashSet::new();
+    for (name, data_type) in columns {
+        let rust_type = rust_type(*data_type, input)
+            .ok_or_else(|| MojoError::UnsupportedColumnType(name.clone()))?;
+        let mut ident = to_identifier(name);
+        if !used.insert(ident.clone()) {
+            let mut n = 2;
+            while !used.insert(format!("{ident}_{n}")) {
+                n += 1;
+            }
+            ident = format!("{ident}_{n}");
+        }
+        if &ident != name {
+            writeln!(out, "    #[serde(rename = {name:?})]")?;
+        }
+        writeln!(out, "    pub {ident}: {rust_type},")?;
+    }
+    Ok(())
+}
+
+/// Rust type representing values of given column type.
+/// Inputs are always optional, so that they can be left missing; outputs are optional where NA can occur.
+fn rust_type(data_type: MOJO_DataType, input: bool) -> Option<&'static str> {
+    Some(match data_type {
+        MOJO_DataType::MOJO_BOOL => if input { "Option<bool>" } else { "bool" },
+        MOJO_DataType::MOJO_INT32 => "Option<i32>",
+        MOJO_DataType::MOJO_INT64 => "Option<i64>",
+        MOJO_DataType::MOJO_FLOAT => "Option<f32>",
+        MOJO_DataType::MOJO_DOUBLE => "Option<f64>",
+        MOJO_DataType::MOJO_STRING => if input { "Option<String>" } else { "String" },
+        MOJO_DataType::MOJO_UNKNOWN => return None,
+    })
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "do", "final", "macro", "override", "priv",
+    "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Convert column name to a snake_case Rust identifier.
+fn to_identifier(name: &str) -> String {
+    let mut ident = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            ident.push(c.to_ascii_lowercase());
+        } else if !ident.ends_with('_') {
+            ident.push('_');
+        }
+    }
+    let ident = ident.trim_matches('_');
+    if ident.is_empty() {
+        return "column".to_string();
+    }
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        return format!("_{ident}");
+    }
+    if KEYWORDS.contains(&ident) {
+        return format!("{ident}_");
+    }
+    ident.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::daimojo_library::MOJO_DataType::{MOJO_BOOL, MOJO_DOUBLE, MOJO_FLOAT, MOJO_INT32, MOJO_INT64, MOJO_STRING};
+    use super::{render, to_identifier};
+
+    #[test]
+    fn identifiers() {
+        assert_eq!("fixed_acidity", to_identifier("fixed acidity"));
+        assert_eq!("quality_3", to_identifier("quality.3"));
+        assert_eq!("sepal_len", to_identifier("Sepal_Len"));
+        assert_eq!("_3d", to_identifier("3D"));
+        assert_eq!("type_", to_identifier("type"));
+        assert_eq!("column", to_identifier("%%"));
+    }
+
+    #[test]
+    fn render_wine_like() {
+        let features = vec![
+            ("fixed acidity".to_string(), MOJO_FLOAT),
+            ("count".to_string(), MOJO_INT32),
+            ("Count".to_string(), MOJO_STRING),
+            ("red".to_string(), MOJO_BOOL),
+            ("id".to_string(), MOJO_INT64),
+            ("type".to_string(), MOJO_DOUBLE),
+        ];
+        let outputs = vec![("quality.3".to_string(), MOJO_FLOAT), ("label".to_string(), MOJO_STRING), ("good".to_string(), MOJO_BOOL)];
+        let mut out = Vec::new();
+        render("uuid", &features, &outputs, &mut out).unwrap();
+        let code = String::from_utf8(out).unwrap();
+        assert!(code.contains("    #[serde(rename = \"fixed acidity\")]\n    pub fixed_acidity: Option<f32>,\n"));
+        assert!(code.contains("    pub count: Option<i32>,\n"));
+        assert!(code.contains("    #[serde(rename = \"Count\")]\n    pub count_2: Option<String>,\n"));
+        assert!(code.contains("    #[serde(rename = \"quality.3\")]\n    pub quality_3: Option<f32>,\n"));
+        assert!(code.contains("    pub fn score(&self, rows: &[Input]) -> daimojo::Result<Vec<Output>> {"));
+        // compiled and used by tests/codegen.rs
+        assert_eq!(include_str!("../tests/codegen/wine_like.rs"), code);
+    }
+}
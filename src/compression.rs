@@ -0,0 +1,219 @@
+//! Transparent compression of CSV input and output, with gzip or zstd
+//!
+//! Input is recognized by its magic bytes, so that it works for stdin too;
+//! output compression follows the file extension.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+/// Uncompressed bytes decompressed by [estimate_uncompressed_len] to measure the ratio.
+const RATIO_SAMPLE_LEN: u64 = 4 << 20;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Parses `none`, `gzip` (or `gz`) and `zstd` (or `zst`).
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression '{s}', expected one of: none, gzip, zstd")),
+        }
+    }
+}
+
+impl Compression {
+    /// Compression implied by the file extension, `.gz` or `.zst`.
+    pub fn from_extension(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// Recognize compressed data by its magic bytes, without consuming them.
+    pub fn detect<R: BufRead>(input: &mut R) -> io::Result<Self> {
+        let head = input.fill_buf()?;
+        Ok(if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        })
+    }
+
+    pub fn reader<R: BufRead + Send + 'static>(self, input: R) -> io::Result<Box<dyn Read + Send>> {
+        Ok(match self {
+            Compression::None => Box::new(input),
+            // concatenated members, as produced by parallel compressors, are one stream
+            Compression::Gzip => Box::new(MultiGzDecoder::new(input)),
+            Compression::Zstd => Box::new(zstd::Decoder::with_buffer(input)?),
+        })
+    }
+
+    pub fn writer<W: Write>(self, output: W) -> io::Result<CompressedWriter<W>> {
+        Ok(match self {
+            Compression::None => CompressedWriter::Plain(output),
+            Compression::Gzip => CompressedWriter::Gzip(GzEncoder::new(output, flate2::Compression::default())),
+            Compression::Zstd => CompressedWriter::Zstd(zstd::Encoder::new(output, zstd::DEFAULT_COMPRESSION_LEVEL)?),
+        })
+    }
+}
+
+/// Output written through the compressor; it must be completed with [CompressedWriter::finish].
+pub enum CompressedWriter<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    /// Write the end of the compressed stream and flush it.
+    pub fn finish(self) -> io::Result<W> {
+        let mut output = match self {
+            CompressedWriter::Plain(output) => output,
+            CompressedWriter::Gzip(encoder) => encoder.finish()?,
+            CompressedWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        output.flush()?;
+        Ok(output)
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CompressedWriter::Plain(output) => output.write(buf),
+            CompressedWriter::Gzip(encoder) => encoder.write(buf),
+            CompressedWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CompressedWriter::Plain(output) => output.flush(),
+            CompressedWriter::Gzip(encoder) => encoder.flush(),
+            CompressedWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Open the file, or stdin for `None`, decompressing it; compression is detected when not given.
+pub fn open_input(path: Option<&Path>, compression: Option<Compression>) -> io::Result<Box<dyn Read + Send>> {
+    match path {
+        None => open_reader(BufReader::new(io::stdin()), compression),
+        Some(path) => open_reader(BufReader::new(File::open(path)?), compression),
+    }
+}
+
+fn open_reader<R: BufRead + Send + 'static>(mut input: R, compression: Option<Compression>) -> io::Result<Box<dyn Read + Send>> {
+    let compression = match compression {
+        Some(compression) => compression,
+        None => Compression::detect(&mut input)?,
+    };
+    compression.reader(input)
+}
+
+/// Size of the file after decompression, extrapolated from the ratio of its beginning.
+pub fn estimate_uncompressed_len(path: &Path, compression: Option<Compression>) -> io::Result<u64> {
+    let len = std::fs::metadata(path)?.len();
+    let mut file = BufReader::new(File::open(path)?);
+    let compression = match compression {
+        Some(compression) => compression,
+        None => Compression::detect(&mut file)?,
+    };
+    if compression == Compression::None {
+        return Ok(len);
+    }
+    let consumed = Arc::new(AtomicU64::new(0));
+    let counting = CountingReader { inner: file, count: consumed.clone() };
+    let decoder = compression.reader(BufReader::new(counting))?;
+    let decompressed = io::copy(&mut decoder.take(RATIO_SAMPLE_LEN), &mut io::sink())?;
+    if decompressed < RATIO_SAMPLE_LEN {
+        // all of it
+        return Ok(decompressed);
+    }
+    let ratio = decompressed as f64 / consumed.load(Ordering::Relaxed).max(1) as f64;
+    Ok((len as f64 * ratio) as u64)
+}
+
+struct CountingReader<R> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::path::Path;
+    use crate::test_support::TempDir;
+    use super::{estimate_uncompressed_len, open_reader, Compression};
+
+    fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+        let mut writer = compression.writer(Vec::new()).unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = b"a,b\n1,2\n3,4\n".repeat(100);
+        for compression in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let compressed = compress(compression, &data);
+            assert_eq!(compression, Compression::detect(&mut compressed.as_slice()).unwrap());
+            let mut decompressed = Vec::new();
+            open_reader(std::io::Cursor::new(compressed), None).unwrap()
+                .read_to_end(&mut decompressed).unwrap();
+            assert_eq!(data, decompressed, "{compression:?}");
+        }
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Compression::Gzip, Compression::from_extension(Path::new("data/wine.csv.gz")));
+        assert_eq!(Compression::Zstd, Compression::from_extension(Path::new("wine.csv.zst")));
+        assert_eq!(Compression::None, Compression::from_extension(Path::new("wine.csv")));
+        assert_eq!(Ok(Compression::Gzip), "gz".parse());
+        assert!("lz4".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn uncompressed_len() {
+        let dir = TempDir::new("uncompressed_len");
+        let dir = dir.path();
+        let data = b"x,y\n0.5,text\n".repeat(1000);
+        let path = dir.join("small.csv.zst");
+        std::fs::write(&path, compress(Compression::Zstd, &data)).unwrap();
+        assert_eq!(data.len() as u64, estimate_uncompressed_len(&path, None).unwrap());
+        let path = dir.join("small.csv");
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(data.len() as u64, estimate_uncompressed_len(&path, None).unwrap());
+    }
+}
@@ -1,70 +1,204 @@
 use csv::Writer;
-use std::io::Stdout;
+use std::io::{Stdout, Write};
+use std::time::{Duration, Instant};
 use crate::daimojo_library::{MOJO_DataType, RawColumnBuffer, RawFrame, RawPipeline};
-use crate::error;
+use crate::bool_format::BoolFormat;
+use crate::number_format::NumberFormat;
+use crate::{error, MojoError};
 
-pub struct FrameExporter<'a> {
+/// Text representation of values in CSV, on import and export.
+#[derive(Clone, Debug, Default)]
+pub struct CsvFormat {
+    pub bools: BoolFormat,
+    pub numbers: NumberFormat,
+}
+
+/// Capacity of the output buffer; a batch is formatted into it and written out in chunks of this size.
+pub const BUFFER_CAPACITY: usize = 256 * 1024;
+
+/// When buffered output is flushed to the underlying writer.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FlushPolicy {
+    /// After every row, for interactive streaming
+    Line,
+    /// After every batch
+    #[default]
+    Batch,
+    /// After a batch, if the given time passed since the previous flush
+    Interval(Duration),
+}
+
+pub struct FrameExporter<'a, W: Write = Stdout> {
     pub saved_batches: usize,
     pub saved_rows: usize,
-    wtr: Writer<Stdout>,
+    wtr: Writer<W>,
     ocols: Vec<RawColumnBuffer<'a>>,
+    ocol_names: Vec<String>,
+    bool_format: BoolFormat,
+    number_format: NumberFormat,
+    flush_policy: FlushPolicy,
+    last_flush: Instant,
+    /// Text of the current numeric field, reused for all of them
+    field: String,
 }
 
 impl<'a> FrameExporter<'a> {
     pub fn init(pipeline: &RawPipeline, frame: &'a RawFrame) -> error::Result<Self> {
-        let mut wtr = csv::Writer::from_writer(std::io::stdout());
+        Self::with_writer(pipeline, frame, std::io::stdout())
+    }
+}
+
+impl<'a, W: Write> FrameExporter<'a, W> {
+    pub fn with_writer(pipeline: &RawPipeline, frame: &'a RawFrame, writer: W) -> error::Result<Self> {
+        let mut wtr = csv::WriterBuilder::new().buffer_capacity(BUFFER_CAPACITY).from_writer(writer);
         let mut ocols = Vec::new();
+        let mut ocol_names = Vec::new();
         for (index, name) in pipeline.output_names_iter().enumerate() {
-            wtr.write_field(name.to_string_lossy().as_ref())?;
+            let name = name.to_string_lossy();
+            wtr.write_field(name.as_ref())?;
             // println!("Rust: output_data({index}='{}') -> {:X}", col.name, ptr as usize);
-            ocols.push(frame.output_col(index)?);
+            let col = frame.output_col(index)?;
+            if col.data_type == MOJO_DataType::MOJO_UNKNOWN {
+                return Err(MojoError::UnsupportedColumnType(name.to_string()));
+            }
+            ocols.push(col);
+            ocol_names.push(name.to_string());
         }
         wtr.write_record(None::<&[u8]>)?;
         wtr.flush()?;
-        Ok(Self { saved_batches: 0, saved_rows:0, wtr, ocols})
+        Ok(Self {
+            saved_batches: 0, saved_rows:0, wtr, ocols, ocol_names,
+            bool_format: BoolFormat::default(),
+            number_format: NumberFormat::default(),
+            flush_policy: FlushPolicy::default(),
+            last_flush: Instant::now(),
+            field: String::new(),
+        })
     }
 
-    pub fn export_frame(&mut self, rows: usize) -> std::io::Result<()> {
+    /// Set when output is flushed; by default, after every batch.
+    pub fn with_flush_policy(mut self, flush_policy: FlushPolicy) -> Self {
+        self.flush_policy = flush_policy;
+        self
+    }
+
+    /// Set rendering of numeric columns.
+    pub fn with_number_format(mut self, number_format: NumberFormat) -> Self {
+        self.number_format = number_format;
+        self
+    }
+
+    /// Set rendering of boolean columns.
+    pub fn with_bool_format(mut self, bool_format: BoolFormat) -> Self {
+        self.bool_format = bool_format;
+        self
+    }
+
+    pub fn export_frame(&mut self, rows: usize) -> error::Result<()> {
         RawColumnBuffer::reset_current(&mut self.ocols);
         for row in 0..rows {
-            for col in &mut self.ocols {
-                let s = Self::item_to_string(row, col);
-                self.wtr.write_field(s)?;
+            for (col, name) in self.ocols.iter_mut().zip(&self.ocol_names) {
+                Self::write_item(&mut self.wtr, &mut self.field, row, col, name, &self.bool_format, &self.number_format)?;
             }
             self.wtr.write_record(None::<&[u8]>)?;
-            self.wtr.flush()?;
+            if self.flush_policy == FlushPolicy::Line {
+                self.wtr.flush()?;
+            }
+        }
+        match self.flush_policy {
+            FlushPolicy::Line => {}
+            FlushPolicy::Batch => self.flush()?,
+            FlushPolicy::Interval(interval) => {
+                if self.last_flush.elapsed() >= interval {
+                    self.flush()?;
+                }
+            }
         }
         self.saved_batches += 1;
         self.saved_rows += rows;
         Ok(())
     }
 
-    fn item_to_string(row: usize, col: &mut RawColumnBuffer) -> String {
+    /// Write all buffered output. Must be called after the last batch, to see errors of the final write.
+    pub fn flush(&mut self) -> error::Result<()> {
+        self.wtr.flush()?;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    /// Write the value as the next field; numbers are formatted into `field` first, other values are written directly.
+    fn write_item(wtr: &mut Writer<W>, field: &mut String, row: usize, col: &mut RawColumnBuffer, name: &str, bool_format: &BoolFormat, format: &NumberFormat) -> error::Result<()> {
+        field.clear();
         match col.data_type {
-            MOJO_DataType::MOJO_BOOL => {
-                let value = col.unchecked_read_next::<bool>();
-                format!("{value}")
-            }
-            MOJO_DataType::MOJO_FLOAT => {
-                let value = col.unchecked_read_next::<f32>();
-                format!("{value}")
-            }
-            MOJO_DataType::MOJO_DOUBLE => {
-                let value = col.unchecked_read_next::<f64>();
-                format!("{value}")
-            }
-            MOJO_DataType::MOJO_INT32 => {
-                let value = col.unchecked_read_next::<i32>();
-                format!("{value}")
-            }
-            MOJO_DataType::MOJO_INT64 => {
-                let value = col.unchecked_read_next::<i64>();
-                format!("{value}")
-            }
-            MOJO_DataType::MOJO_STRING => {
-                col.unchecked_read_string(row).to_string()
-            }
-            MOJO_DataType::MOJO_UNKNOWN => panic!("unsupported column type")
+            MOJO_DataType::MOJO_BOOL => return Ok(wtr.write_field(bool_format.render(col.read_next::<bool>()?.unwrap_or_default()))?),
+            MOJO_DataType::MOJO_FLOAT => format.write_f32(field, col.read_next()?),
+            MOJO_DataType::MOJO_DOUBLE => format.write_f64(field, col.read_next()?),
+            MOJO_DataType::MOJO_INT32 => format.write_int(field, col.read_next::<i32>()?),
+            MOJO_DataType::MOJO_INT64 => format.write_int(field, col.read_next::<i64>()?),
+            MOJO_DataType::MOJO_STRING => return Ok(wtr.write_field(col.unchecked_read_string(row).as_bytes())?),
+            MOJO_DataType::MOJO_UNKNOWN => return Err(MojoError::UnsupportedColumnType(name.to_string())),
         }
+        wtr.write_field(field.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+    use crate::{DaiMojoLibrary, FrameImporter, MOJO_Transform_Ops, RawFrame, RawModel, RawPipeline};
+    use crate::test_support::{FlushCounter, ECHO_MODEL};
+    use super::{FlushPolicy, FrameExporter};
+
+    #[test]
+    fn flush_policies() {
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, "data/iris/pipeline.mojo", "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let frame = RawFrame::new(&pipeline, 10).unwrap();
+        let flushes = |policy: FlushPolicy| {
+            let counter = Rc::new(Cell::new(0));
+            let mut exporter = FrameExporter::with_writer(&pipeline, &frame, FlushCounter(counter.clone())).unwrap()
+                .with_flush_policy(policy);
+            // header
+            assert_eq!(1, counter.get());
+            for _ in 0..3 {
+                exporter.export_frame(10).unwrap();
+            }
+            assert_eq!(30, exporter.saved_rows);
+            counter.get() - 1
+        };
+        assert_eq!(30, flushes(FlushPolicy::Line));
+        assert_eq!(3, flushes(FlushPolicy::Batch));
+        assert_eq!(0, flushes(FlushPolicy::Interval(Duration::from_secs(3600))));
+        assert_eq!(3, flushes(FlushPolicy::Interval(Duration::ZERO)));
+    }
+
+    #[test]
+    fn echoed_fields() {
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, ECHO_MODEL, "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let frame = RawFrame::new(&pipeline, 10).unwrap();
+        let data = "string,double,float,int64,int32,bool\n\
+            a,2.25,1.5,-2,1,yes\n\
+            ,,,,,\n\
+            \"x,y\",-1e-7,0.1,9007199254740993,-2147483647,0\n";
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let mut importer = FrameImporter::init(&pipeline, &frame, &mut rdr).unwrap();
+        let rows = importer.import_batch(&mut rdr).unwrap().unwrap();
+        pipeline.transform(&frame, rows, false).unwrap();
+        let mut out = Vec::new();
+        let mut exporter = FrameExporter::with_writer(&pipeline, &frame, &mut out).unwrap();
+        exporter.export_frame(rows).unwrap();
+        exporter.flush().unwrap();
+        drop(exporter);
+        // missing numbers round-trip as NA, written as the stored values; a missing bool is false
+        assert_eq!("bool_out,int32_out,int64_out,float_out,double_out,string_out\n\
+            true,1,-2,1.5,2.25,a\n\
+            false,2147483647,9223372036854775807,NaN,NaN,\n\
+            false,-2147483647,9007199254740993,0.1,-0.0000001,\"x,y\"\n", String::from_utf8(out).unwrap());
     }
 }
@@ -1,17 +1,27 @@
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Read};
 use std::collections::HashMap;
-use crate::daimojo_library::{MOJO_DataType, MOJO_INT32_NAN, MOJO_INT64_NAN, RawColumnBuffer, RawFrame, RawPipeline};
+use std::str::FromStr;
+use crate::daimojo_library::{MOJO_DataType, RawColumnBuffer, RawFrame, RawPipeline};
+use crate::bool_format::BoolFormat;
 use crate::{error, MojoError};
 
 pub struct FrameImporter<'a> {
     icols: Vec<RawColumnBuffer<'a>>,
+    icol_names: Vec<String>,
     csv_indices: Vec<usize>,
+    /// Rows imported per batch, up to the frame size
     batch_size: usize,
+    capacity: usize,
     eof: bool,
+    bool_format: BoolFormat,
+    /// Reused by [FrameImporter::import_batch]
+    record: csv::ByteRecord,
+    /// NUL-terminated copy of the string value being written
+    scratch: Vec<u8>,
 }
 
 impl<'a> FrameImporter<'a> {
-    pub fn init(pipeline: &RawPipeline, frame: &'a RawFrame, rdr: &mut csv::Reader<std::fs::File>) -> error::Result<Self> {
+    pub fn init<R: Read>(pipeline: &RawPipeline, frame: &'a RawFrame, rdr: &mut csv::Reader<R>) -> error::Result<Self> {
         let model = pipeline.model;
         let csv_headers = match rdr.byte_headers() {
             Err(e) => Err(std::io::Error::new(ErrorKind::InvalidData, format!("Cannot read header: {e}")))?,
@@ -21,12 +31,18 @@ impl<'a> FrameImporter<'a> {
             .map(|(csv_index, col_name)| (col_name, csv_index))
             .collect();
         let mut icols = Vec::new();
+        let mut icol_names = Vec::new();
         let mut csv_indices = Vec::new();
         let mut missing_data = None;
         for (index, name) in model.feature_names_iter().enumerate() {
             if let Some(&csv_index) = csv_headers.get(name.to_bytes()) {
                 // println!("Rust: input_data({index}='{}') -> {:X}", col.name, ptr as usize);
-                icols.push(frame.input_col(index)?);
+                let col = frame.input_col(index)?;
+                if col.data_type == MOJO_DataType::MOJO_UNKNOWN {
+                    return Err(MojoError::UnsupportedColumnType(name.to_string_lossy().to_string()));
+                }
+                icols.push(col);
+                icol_names.push(name.to_string_lossy().to_string());
                 csv_indices.push(csv_index);
             } else {
                 log::error!("Unknown input column name: {}", name.to_string_lossy());
@@ -38,13 +54,52 @@ impl<'a> FrameImporter<'a> {
         }
         Ok(Self {
             icols,
+            icol_names,
             csv_indices,
             batch_size: frame.nrow,
-            eof: rdr.is_done()
+            capacity: frame.nrow,
+            eof: rdr.is_done(),
+            bool_format: BoolFormat::default(),
+            record: csv::ByteRecord::new(),
+            scratch: Vec::new(),
         })
     }
 
-    pub fn import_frame(&mut self, rdr_iter: &mut csv::StringRecordsIter<std::fs::File>) -> std::io::Result<Option<usize>> {
+    /// Set tokens recognized in boolean columns.
+    pub fn with_bool_format(mut self, bool_format: BoolFormat) -> Self {
+        self.bool_format = bool_format;
+        self
+    }
+
+    /// Import at most `rows` rows per batch from now on; the frame size is the limit.
+    pub fn set_batch_size(&mut self, rows: usize) {
+        self.batch_size = rows.clamp(1, self.capacity);
+    }
+
+    /// Import the next batch from the reader; `None` when the input is exhausted.
+    /// Records are read as bytes into one reused record, so nothing is allocated per row or per value.
+    pub fn import_batch<R: Read>(&mut self, rdr: &mut csv::Reader<R>) -> error::Result<Option<usize>> {
+        if self.eof {
+            return Ok(None);
+        }
+        RawColumnBuffer::reset_current(&mut self.icols);
+        let mut record = std::mem::take(&mut self.record);
+        let mut row = 0;
+        while row < self.batch_size {
+            if !rdr.read_byte_record(&mut record)? {
+                self.eof = true;
+                break;
+            }
+            self.import_record(row, &record)?;
+            row += 1;
+        }
+        self.record = record;
+        Ok(if row == 0 { None } else { Some(row) })
+    }
+
+    /// Import the next batch from string records. Each record is allocated and validated as UTF-8;
+    /// [FrameImporter::import_batch] avoids that.
+    pub fn import_frame<R: Read>(&mut self, rdr_iter: &mut csv::StringRecordsIter<R>) -> error::Result<Option<usize>> {
         let mut row = 0;
         if self.eof {
             return Ok(None);
@@ -52,12 +107,7 @@ impl<'a> FrameImporter<'a> {
         RawColumnBuffer::reset_current(&mut self.icols);
         for record in rdr_iter {
             let record = record?;
-            // fill mojo row
-            for (feature_index, col) in &mut self.icols.iter_mut().enumerate() {
-                let csv_index = self.csv_indices[feature_index];
-                let value = record.get(csv_index).expect("column disappeared?");
-                Self::item_from_str(row, col, value);
-            }
+            self.import_record(row, record.as_byte_record())?;
             row += 1;
             if row == self.batch_size {
                 return Ok(Some(row))
@@ -68,51 +118,98 @@ impl<'a> FrameImporter<'a> {
         Ok(if row == 0 { None } else { Some(row) })
     }
 
-    fn item_from_str(row: usize, col: &mut RawColumnBuffer, value: &str) {
-        // log::trace!("memset:{:?}:[@0x{:x}] = '{value}'", col.data_type, col.current as usize);
+    /// Import records that were already read, for example to feed the same batch into more frames.
+    /// Returns the number of imported rows; records beyond the frame size are ignored.
+    pub fn import_records(&mut self, records: &[csv::StringRecord]) -> error::Result<usize> {
+        self.import_all(records.iter().map(csv::StringRecord::as_byte_record))
+    }
+
+    /// Like [FrameImporter::import_records], for records read as bytes.
+    pub fn import_byte_records(&mut self, records: &[csv::ByteRecord]) -> error::Result<usize> {
+        self.import_all(records.iter())
+    }
+
+    fn import_all<'r>(&mut self, records: impl Iterator<Item = &'r csv::ByteRecord>) -> error::Result<usize> {
+        RawColumnBuffer::reset_current(&mut self.icols);
+        let mut rows = 0;
+        for record in records.take(self.batch_size) {
+            self.import_record(rows, record)?;
+            rows += 1;
+        }
+        Ok(rows)
+    }
+
+    fn import_record(&mut self, row: usize, record: &csv::ByteRecord) -> error::Result<()> {
+        // fill mojo row
+        for (feature_index, col) in &mut self.icols.iter_mut().enumerate() {
+            let csv_index = self.csv_indices[feature_index];
+            let value = record.get(csv_index)
+                .ok_or_else(|| MojoError::MissingValue {
+                    line: record.position().map_or(0, |p| p.line()),
+                    column: self.icol_names[feature_index].clone(),
+                })?;
+            Self::item_from_bytes(row, col, value, &self.icol_names[feature_index], &self.bool_format, &mut self.scratch)?;
+        }
+        Ok(())
+    }
+
+    fn item_from_bytes(row: usize, col: &mut RawColumnBuffer, value: &[u8], name: &str, bool_format: &BoolFormat, scratch: &mut Vec<u8>) -> error::Result<()> {
+        // log::trace!("memset:{:?}:[@0x{:x}] = '{value:?}'", col.data_type, col.current as usize);
         match col.data_type {
             MOJO_DataType::MOJO_BOOL => {
-                let value = mojo2_parse_bool(value);
-                col.unchecked_write_next(value);
-            }
-            MOJO_DataType::MOJO_FLOAT => {
-                let value = value.parse::<f32>().unwrap_or(f32::NAN);
-                col.unchecked_write_next(value);
-            }
-            MOJO_DataType::MOJO_DOUBLE => {
-                let value = value.parse::<f64>().unwrap_or(f64::NAN);
-                col.unchecked_write_next(value);
-            }
-            MOJO_DataType::MOJO_INT32 => {
-                //TODO: if the string has decimal places, parse it and change to int
-                let value = value.parse::<i32>().unwrap_or(MOJO_INT32_NAN);
-                col.unchecked_write_next(value);
-            }
-            MOJO_DataType::MOJO_INT64 => {
-                let value = value.parse::<i64>().unwrap_or(MOJO_INT64_NAN);
-                col.unchecked_write_next(value);
+                let value = std::str::from_utf8(value).map_or(bool_format.na_value, |s| bool_format.parse_or_na(s));
+                col.write_next(Some(value))?
             }
+            MOJO_DataType::MOJO_FLOAT => col.write_next(parse::<f32>(value))?,
+            MOJO_DataType::MOJO_DOUBLE => col.write_next(parse::<f64>(value))?,
+            //TODO: if the string has decimal places, parse it and change to int
+            MOJO_DataType::MOJO_INT32 => col.write_next(parse::<i32>(value))?,
+            MOJO_DataType::MOJO_INT64 => col.write_next(parse::<i64>(value))?,
             MOJO_DataType::MOJO_STRING => {
-                col.unchecked_write_str(row, value);
+                col.unchecked_write_bytes(row, value, scratch);
             }
-            MOJO_DataType::MOJO_UNKNOWN => panic!("unsupported column type")
+            MOJO_DataType::MOJO_UNKNOWN => return Err(MojoError::UnsupportedColumnType(name.to_string())),
         }
+        Ok(())
     }
 }
 
-fn mojo2_parse_bool(s: &str) -> bool {
-    const VALUES: &[&str] = &[
-        "true", "True", "TRUE", "1", "1.0",
-        "false", "False", "FALSE", "0", "0.0"];
-    let mut result = true;
-    for &item in VALUES {
-        if item == "false" {
-            result = false;
-        }
-        if item == s {
-            return result;
-        }
+/// Parse a number from field bytes, without copying them; invalid text, including non-UTF-8, is NA.
+fn parse<T: FromStr>(value: &[u8]) -> Option<T> {
+    std::str::from_utf8(value).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DaiMojoLibrary, MOJO_Transform_Ops, RawFrame, RawModel, RawPipeline};
+    use super::{parse, FrameImporter};
+
+    #[test]
+    fn parse_bytes() {
+        assert_eq!(Some(1.5f32), parse(b"1.5"));
+        assert_eq!(Some(-7i64), parse(b"-7"));
+        assert_eq!(None, parse::<i32>(b"1.5"));
+        assert_eq!(None, parse::<f64>(b""));
+        assert_eq!(None, parse::<f64>(b"\xff1"));
+    }
+
+    #[test]
+    fn batches() {
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, "data/iris/pipeline.mojo", "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let frame = RawFrame::new(&pipeline, 10).unwrap();
+        let data: String = std::iter::once("x\n".to_string()).chain((0..25).map(|i| format!("{i}\n"))).collect();
+
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let mut importer = FrameImporter::init(&pipeline, &frame, &mut rdr).unwrap();
+        let batches: Vec<usize> = std::iter::from_fn(|| importer.import_batch(&mut rdr).unwrap()).collect();
+        assert_eq!(vec![10, 10, 5], batches);
+
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        let mut importer = FrameImporter::init(&pipeline, &frame, &mut rdr).unwrap();
+        let mut records = rdr.records();
+        let batches: Vec<usize> = std::iter::from_fn(|| importer.import_frame(&mut records).unwrap()).collect();
+        assert_eq!(vec![10, 10, 5], batches);
     }
-    log::trace!("Invalid bool value: '{s}'");
-    false
 }
@@ -3,19 +3,24 @@
 #![allow(non_snake_case)]
 
 use std::borrow::Cow;
-use std::ffi::{CStr, CString};
-use std::io::ErrorKind;
+use std::ffi::{c_void, CStr, CString};
+use std::io::{ErrorKind, Read};
 use std::mem::transmute;
 use std::os::raw::c_char;
-use std::path::Path;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::ptr::slice_from_raw_parts;
+use std::sync::OnceLock;
 use bitflags::bitflags;
 
-use chrono::{DateTime, NaiveDateTime, Utc};
-use dlopen2::wrapper::{Container, WrapperApi};
+use chrono::{DateTime, Utc};
+use dlopen2::raw::Library;
+use dlopen2::wrapper::WrapperApi;
 
+use crate::version::{self, BindingSet, Compatibility, RuntimeVersion, VersionRange};
 use crate::carray::{CArrayIterator, CTwinArrayIterator, pchar_to_cowstr};
-use crate::{error, MojoError};
+use crate::license::{license_env_vars, LICENSE_ENV_VARS};
+use crate::{discovery, error, MojoError};
 
 #[allow(non_camel_case_types)]
 #[repr(C)]
@@ -51,13 +56,72 @@ pub struct MOJO_Pipeline {
 #[allow(non_camel_case_types)]
 pub struct MOJO_Frame {}
 
+/// Rust type of values in columns of one [MOJO_DataType], with its representation of missing value (NA).
+pub trait ColumnValue: Copy {
+    const DATA_TYPE: MOJO_DataType;
+    /// Value stored in the column for NA
+    const NA: Self;
+    fn is_na(&self) -> bool;
+
+    /// Read next value, `None` for NA. Types stored in other representation than their own override this.
+    fn read_from(col: &mut RawColumnBuffer) -> Option<Self> {
+        let value: Self = col.unchecked_read_next();
+        if value.is_na() { None } else { Some(value) }
+    }
+
+    /// Write next value, `None` as NA.
+    fn write_to(value: Option<Self>, col: &mut RawColumnBuffer) {
+        col.unchecked_write_next(value.unwrap_or(Self::NA))
+    }
+}
+
+impl ColumnValue for f32 {
+    const DATA_TYPE: MOJO_DataType = MOJO_DataType::MOJO_FLOAT;
+    const NA: Self = f32::NAN;
+    fn is_na(&self) -> bool { self.is_nan() }
+}
+
+impl ColumnValue for f64 {
+    const DATA_TYPE: MOJO_DataType = MOJO_DataType::MOJO_DOUBLE;
+    const NA: Self = f64::NAN;
+    fn is_na(&self) -> bool { self.is_nan() }
+}
+
+impl ColumnValue for i32 {
+    const DATA_TYPE: MOJO_DataType = MOJO_DataType::MOJO_INT32;
+    const NA: Self = MOJO_INT32_NAN;
+    fn is_na(&self) -> bool { *self == MOJO_INT32_NAN }
+}
+
+impl ColumnValue for i64 {
+    const DATA_TYPE: MOJO_DataType = MOJO_DataType::MOJO_INT64;
+    const NA: Self = MOJO_INT64_NAN;
+    fn is_na(&self) -> bool { *self == MOJO_INT64_NAN }
+}
+
+/// Booleans are stored as [i8] and have no NA; it is written as `false` and never read.
+impl ColumnValue for bool {
+    const DATA_TYPE: MOJO_DataType = MOJO_DataType::MOJO_BOOL;
+    const NA: Self = false;
+    fn is_na(&self) -> bool { false }
+
+    /// Any non-zero byte is true.
+    fn read_from(col: &mut RawColumnBuffer) -> Option<Self> {
+        Some(col.unchecked_read_next::<i8>() != 0)
+    }
+
+    fn write_to(value: Option<Self>, col: &mut RawColumnBuffer) {
+        col.unchecked_write_next(value.unwrap_or(Self::NA) as i8)
+    }
+}
+
 #[allow(dead_code)]
 #[allow(non_camel_case_types)]
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum MOJO_DataType {
     MOJO_UNKNOWN = 0,
-    /// [i8] byte-represented boolean, 0=false, 1=true, NA is not defined
+    /// [i8] byte-represented boolean, 0=false, 1=true; no NA
     MOJO_BOOL = 1,
     /// [i32] 4 bytes signed integer
     MOJO_INT32 = 2,
@@ -87,12 +151,6 @@ bitflags! {
     }
 }
 
-#[derive(dlopen2_derive::WrapperApi)]
-pub struct DaiMojoVersionBindings {
-    #[dlopen2_name = "MOJO_Version"]
-    mojo_version: unsafe extern "C" fn() -> *const c_char,
-}
-
 #[derive(dlopen2_derive::WrapperApi)]
 pub struct DaiMojoBindings {
     // Model
@@ -108,37 +166,210 @@ pub struct DaiMojoBindings {
     MOJO_FrameNcol: unsafe extern "C" fn(frame: *const MOJO_Frame) -> usize,
     MOJO_Input_Data: unsafe extern "C" fn(pipeline: *const MOJO_Pipeline, frame: *const MOJO_Frame, index: usize) -> *mut u8,
     MOJO_Output_Data: unsafe extern "C" fn(pipeline: *const MOJO_Pipeline, frame: *const MOJO_Frame, index: usize) -> *const u8,
-    // String values support
+}
+
+/// String values support; optional, resolved on first use.
+#[derive(dlopen2_derive::WrapperApi)]
+pub struct DaiMojoStringBindings {
     MOJO_Column_Write_Str: unsafe extern "C" fn(buffer: *mut u8, index: usize, value: *const c_char),
     MOJO_Column_Read_Str: unsafe extern "C" fn(buffer: *const u8, index: usize) -> *const c_char,
 }
 
+const VERSION_SYMBOL: &str = "MOJO_Version";
+const REQUIRED_SYMBOLS: &[&str] = &[
+    "MOJO_Version",
+    "MOJO_NewModel", "MOJO_DeleteModel",
+    "MOJO_NewPipeline", "MOJO_DeletePipeline", "MOJO_Transform",
+    "MOJO_Pipeline_NewFrame", "MOJO_DeleteFrame", "MOJO_FrameNcol", "MOJO_Input_Data", "MOJO_Output_Data",
+];
+const STRING_SYMBOLS: &[&str] = &["MOJO_Column_Write_Str", "MOJO_Column_Read_Str"];
+
+/// Which API functions the loaded library provides.
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    /// Every known API function with a flag whether it was found
+    pub functions: Vec<(&'static str, bool)>,
+}
+
+impl Capabilities {
+    pub fn has(&self, function: &str) -> bool {
+        self.functions.iter().any(|&(name, found)| found && name == function)
+    }
+
+    /// Columns of type [MOJO_DataType::MOJO_STRING] can be read and written
+    pub fn string_columns(&self) -> bool {
+        STRING_SYMBOLS.iter().all(|name| self.has(name))
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item=&'static str> + '_ {
+        self.functions.iter()
+            .filter(|(_, found)| !found)
+            .map(|&(name, _)| name)
+    }
+}
+
 pub struct DaiMojoLibrary {
-    api: Container<DaiMojoBindings>,
+    api: DaiMojoBindings,
+    strings: OnceLock<Option<DaiMojoStringBindings>>,
     version: String,
+    runtime_version: RuntimeVersion,
+    /// Keeps the library open; must be dropped last
+    lib: Library,
 }
 
+/// The library is opened with `RTLD_LOCAL`, so its symbols are not visible to other libraries.
+/// Several instances loaded from different files, like two releases of daimojo, can be used side by side.
 impl DaiMojoLibrary {
     pub fn load<P: AsRef<Path>>(libfile: P) -> error::Result<Self> {
-        let libfile = libfile.as_ref().canonicalize()?;
-        let libfile = libfile.to_str().expect(&format!("Not a valid unicode pathname: {}", libfile.to_string_lossy()));
-        let version_api: Container<DaiMojoVersionBindings> = unsafe { Container::load(libfile) }
-            .map_err(|e| std::io::Error::new(ErrorKind::InvalidInput, format!("{libfile}: {e:?}")))?;
-        let version = unsafe { CStr::from_ptr(version_api.mojo_version()) }.to_string_lossy();
+        Self::load_accepting(libfile, &VersionRange::default())
+    }
+
+    /// Load the library, failing when its version is outside of the `accepted` range.
+    pub fn load_accepting<P: AsRef<Path>>(libfile: P, accepted: &VersionRange) -> error::Result<Self> {
+        let libfile = canonicalize(libfile.as_ref())?;
+        Self::open(libfile, accepted)
+    }
+
+    /// Find the library at one of [discovery::candidates] and load the first one that works.
+    pub fn discover(explicit: Option<&Path>, mojo: Option<&Path>) -> error::Result<Self> {
+        Self::discover_accepting(explicit, mojo, &VersionRange::default())
+    }
+
+    /// Like [DaiMojoLibrary::discover], skipping libraries with version outside of the `accepted` range.
+    pub fn discover_accepting(explicit: Option<&Path>, mojo: Option<&Path>, accepted: &VersionRange) -> error::Result<Self> {
+        let mut tried = Vec::new();
+        for candidate in discovery::candidates(explicit, mojo) {
+            log::debug!("Trying library: {}", candidate.display());
+            let result = if discovery::is_system_lookup(&candidate) {
+                Self::open(candidate.clone(), accepted)
+            } else {
+                Self::load_accepting(&candidate, accepted)
+            };
+            match result {
+                Ok(lib) => {
+                    log::debug!("Loaded library: {}", candidate.display());
+                    return Ok(lib);
+                }
+                Err(e) => tried.push(format!("{}: {}", candidate.display(), error_chain(&e))),
+            }
+        }
+        Err(MojoError::LibraryNotFound(tried))
+    }
+
+    fn open(libfile: PathBuf, accepted: &VersionRange) -> error::Result<Self> {
+        let load_error = |source| MojoError::LibraryLoadError { path: libfile.clone(), source };
+        let lib = Library::open(&libfile).map_err(load_error)?;
+        let version = unsafe {
+            let mojo_version: unsafe extern "C" fn() -> *const c_char = lib.symbol(VERSION_SYMBOL).map_err(load_error)?;
+            CStr::from_ptr(mojo_version()).to_string_lossy().to_string()
+        };
         log::debug!("Version: {version}");
 
-        if !version.starts_with("2.") {
-            return Err(error::MojoError::UnsupportedApi(libfile.to_string(), version.to_string()));
+        let runtime_version: RuntimeVersion = match version.parse() {
+            Ok(runtime_version) if accepted.contains(&runtime_version) => runtime_version,
+            _ => return Err(MojoError::UnsupportedApi(libfile.display().to_string(), version)),
+        };
+        let bindings = version::compatibility(&runtime_version).map(|entry| entry.bindings);
+        if bindings != Some(BindingSet::V2) {
+            return Err(MojoError::UnsupportedApi(libfile.display().to_string(), version));
         }
-        // TODO: isn't there a way to avoid loading again?
-        let container = unsafe { Container::load(libfile) };
-        let api = container?;
-        Ok(Self { api, version: version.to_string() })
+        let api = unsafe { DaiMojoBindings::load(&lib) }.map_err(load_error)?;
+        Ok(Self { api, strings: OnceLock::new(), version, runtime_version, lib })
     }
 
-    pub fn version(&self) -> Cow<str> {
+    pub fn version(&self) -> Cow<'_, str> {
         Cow::from(&self.version)
     }
+
+    pub fn runtime_version(&self) -> &RuntimeVersion {
+        &self.runtime_version
+    }
+
+    /// Known bindings and quirks of this library's version.
+    pub fn compatibility(&self) -> &'static Compatibility {
+        version::compatibility(&self.runtime_version).expect("checked when opened")
+    }
+
+    /// Probe the library for all known API functions.
+    pub fn capabilities(&self) -> Capabilities {
+        let functions = REQUIRED_SYMBOLS.iter()
+            .chain(STRING_SYMBOLS)
+            .map(|&name| (name, unsafe { self.lib.symbol::<*const c_void>(name) }.is_ok()))
+            .collect();
+        Capabilities { functions }
+    }
+
+    fn strings(&self) -> Option<&DaiMojoStringBindings> {
+        self.strings
+            .get_or_init(|| unsafe { Option::<DaiMojoStringBindings>::load(&self.lib) }.ok().flatten())
+            .as_ref()
+    }
+
+    /// Column buffers of given type can be used with this library.
+    fn check_column_type(&self, data_type: MOJO_DataType) -> error::Result<()> {
+        if data_type == MOJO_DataType::MOJO_STRING && self.strings().is_none() {
+            return Err(MojoError::UnsupportedApi(STRING_SYMBOLS.join(", "), self.version.clone()));
+        }
+        Ok(())
+    }
+}
+
+fn error_chain(e: &dyn std::error::Error) -> String {
+    match e.source() {
+        None => e.to_string(),
+        Some(source) => format!("{e}: {}", error_chain(source)),
+    }
+}
+
+fn canonicalize(path: &Path) -> error::Result<PathBuf> {
+    path.canonicalize()
+        .map_err(|source| MojoError::InvalidPath { path: path.to_path_buf(), source })
+}
+
+/// Convert path to C string from its raw bytes, so that also non-UTF-8 paths can be passed.
+fn path_to_cstring(path: &Path) -> error::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| MojoError::InvalidPath {
+            path: path.to_path_buf(),
+            source: std::io::Error::new(ErrorKind::InvalidInput, "path contains NUL byte"),
+        })
+}
+
+/// Minimal size of a zip archive, which is the size of its "end of central directory" record.
+const MIN_MOJO_SIZE: u64 = 22;
+/// Mojo files are zip archives, starting with a local file header.
+const MOJO_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+/// Check that the file looks like a mojo, before passing it to the native library which cannot tell us what is wrong.
+fn check_model_file(path: &Path) -> error::Result<()> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|source| MojoError::InvalidPath { path: path.to_path_buf(), source })?;
+    let size = file.metadata()?.len();
+    if size < MIN_MOJO_SIZE {
+        return Err(MojoError::CorruptModel { path: path.to_path_buf(), reason: format!("too small ({size} bytes)") });
+    }
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)?;
+    if &magic != MOJO_MAGIC {
+        return Err(MojoError::CorruptModel { path: path.to_path_buf(), reason: "not a zip archive".to_string() });
+    }
+    Ok(())
+}
+
+/// The prefix is passed to the native library as is; it must be empty, an existing path, or a prefix of file names in an existing directory.
+fn check_tf_lib_prefix(tf_lib_prefix: &str) -> error::Result<CString> {
+    let unsupported = || MojoError::UnsupportedTfPrefix(tf_lib_prefix.to_string());
+    if !tf_lib_prefix.is_empty() {
+        let prefix = Path::new(tf_lib_prefix);
+        let dir = match prefix.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if !prefix.exists() && !dir.is_dir() {
+            return Err(unsupported());
+        }
+    }
+    CString::new(tf_lib_prefix).map_err(|_| unsupported())
 }
 
 pub struct RawModel<'a> {
@@ -147,18 +378,33 @@ pub struct RawModel<'a> {
 }
 
 impl<'a> RawModel<'a> {
-    pub fn load<P: AsRef<Path>>(lib: &'a DaiMojoLibrary, filename: P, tf_lib_prefix: &str) -> std::io::Result<Self> {
-        let filename = filename.as_ref().canonicalize()?;
-        let filename = filename.to_str().expect(&format!("Not a valid unicode pathname: {}", filename.to_string_lossy()));
-        let filename = CString::new(filename)?;
-        let tf_lib_prefix = CString::new(tf_lib_prefix)?;
+    pub fn load<P: AsRef<Path>>(lib: &'a DaiMojoLibrary, filename: P, tf_lib_prefix: &str) -> error::Result<Self> {
+        let path = match filename.as_ref().canonicalize() {
+            Ok(path) => path,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Err(MojoError::ModelNotFound(filename.as_ref().to_path_buf())),
+            Err(source) => return Err(MojoError::InvalidPath { path: filename.as_ref().to_path_buf(), source }),
+        };
+        check_model_file(&path)?;
+        let filename = path_to_cstring(&path)?;
+        let tf_lib_prefix = check_tf_lib_prefix(tf_lib_prefix)?;
         let model_ptr = unsafe {
             lib.api.MOJO_NewModel(filename.as_ptr(), tf_lib_prefix.as_ptr())
         };
         if model_ptr.is_null() {
-            return Err(std::io::Error::new(ErrorKind::NotFound, format!("File not found: {}", filename.to_string_lossy())));
+            let license_vars = license_env_vars();
+            if license_vars.is_empty() {
+                log::error!("No license configured; none of these variables is set: {}", LICENSE_ENV_VARS.join(", "));
+                return Err(MojoError::InvalidModel(path));
+            }
+            log::debug!("License configured by: {}", license_vars.join(", "));
+            // the file passed the checks, so the reason is unknown; the runtime may have printed it
+            return Err(MojoError::ModelLoadFailed(path));
         }
-        Ok(Self { lib, model_ptr })
+        let model = Self { lib, model_ptr };
+        if !model.is_valid() {
+            return Err(MojoError::InvalidModel(path));
+        }
+        Ok(model)
     }
 
     #[inline]
@@ -172,7 +418,7 @@ impl<'a> RawModel<'a> {
 
     pub fn dai_version(&self) -> &CStr {
         unsafe {
-            const EMPTY_CSTR: *const c_char = b"?\0".as_ptr().cast();
+            const EMPTY_CSTR: *const c_char = c"?".as_ptr();
             let ptr = (*self.model_ptr).dai_version;
             if ptr.is_null() {
                 log::error!("DAI version is null!");
@@ -188,8 +434,7 @@ impl<'a> RawModel<'a> {
 
     pub fn time_created_utc(&self) -> DateTime<Utc> {
         let time_created = unsafe { (*self.model_ptr).time_created };
-        let n = NaiveDateTime::from_timestamp_opt(time_created as i64, 0).unwrap();
-        DateTime::from_utc(n, Utc)
+        DateTime::from_timestamp(time_created as i64, 0).unwrap_or_default()
     }
 
     pub fn missing_values(&self) -> impl Iterator<Item=&CStr> {
@@ -210,14 +455,16 @@ impl<'a> RawModel<'a> {
         }.map(|(cname, ctype)| (pchar_to_cowstr(cname), ctype))
     }
 
-    pub unsafe fn feature_names(&'a self) -> &[*const c_char] {
+    /// # Safety
+    /// The returned pointers are owned by the native model and must not outlive it.
+    pub unsafe fn feature_names(&'a self) -> &'a [*const c_char] {
         unsafe {
             let ptr = (*self.model_ptr).feature_names;
             let count = (*self.model_ptr).feature_count;
             &*slice_from_raw_parts(ptr, count)
         }
     }
-    pub fn feature_names_iter(&'a self) -> impl Iterator<Item=&CStr> {
+    pub fn feature_names_iter(&'a self) -> impl Iterator<Item=&'a CStr> {
         unsafe {
             self.feature_names().iter()
                 .map(|&s| CStr::from_ptr(s))
@@ -259,7 +506,12 @@ impl<'a> RawPipeline<'a> {
         })
     }
 
-    pub fn output_names(&'a self) -> &[*const c_char] {
+    /// Identity of the native pipeline, stable for its lifetime.
+    pub(crate) fn id(&self) -> usize {
+        self.pipeline_ptr as usize
+    }
+
+    pub fn output_names(&'a self) -> &'a [*const c_char] {
         unsafe {
             let ptr = (*self.pipeline_ptr).output_names;
             let count = (*self.pipeline_ptr).output_count;
@@ -283,7 +535,7 @@ impl<'a> RawPipeline<'a> {
         }
     }
 
-    pub fn output_names_iter(&'a self) -> impl Iterator<Item=&CStr> {
+    pub fn output_names_iter(&'a self) -> impl Iterator<Item=&'a CStr> {
         unsafe {
             self.output_names().iter()
                 .map(|&s| CStr::from_ptr(s))
@@ -353,32 +605,57 @@ impl<'a> RawFrame<'a> {
         }
     }
 
-    pub fn input_col(&self, feature_index: usize) -> error::Result<RawColumnBuffer> {
+    pub fn input_col(&self, feature_index: usize) -> error::Result<RawColumnBuffer<'_>> {
         unsafe {
             let model = (*self.pipeline_ptr).model;
-            let data_type = (*model).feature_types.offset(feature_index as isize).read();
+            let data_type = (*model).feature_types.add(feature_index).read();
             match self.input_data(feature_index) {
                 None => Err(error::MojoError::InvalidInputIndex(feature_index)),
-                Some(ptr) => Ok(RawColumnBuffer::new(self.lib, data_type, ptr))
+                Some(ptr) => RawColumnBuffer::new(self.lib, data_type, ptr),
             }
         }
     }
 
-    pub fn output_col(&self, output_index: usize) -> error::Result<RawColumnBuffer> {
+    pub fn output_col(&self, output_index: usize) -> error::Result<RawColumnBuffer<'_>> {
         unsafe {
-            let data_type = (*self.pipeline_ptr).output_types.offset(output_index as isize).read();
+            let data_type = (*self.pipeline_ptr).output_types.add(output_index).read();
             match self.output_data(output_index) {
                 None => Err(error::MojoError::InvalidOutputIndex(output_index)),
-                Some(ptr) => Ok(RawColumnBuffer::new(self.lib, data_type, ptr)),
+                Some(ptr) => RawColumnBuffer::new(self.lib, data_type, ptr),
+            }
+        }
+    }
+
+    /// Set all input values to missing values of their types; strings to empty string.
+    pub fn clear_inputs(&self) -> error::Result<()> {
+        self.clear_input_rows(self.nrow)
+    }
+
+    /// Like [RawFrame::clear_inputs], for the first `rows` rows only.
+    pub fn clear_input_rows(&self, rows: usize) -> error::Result<()> {
+        let feature_count = unsafe { (*(*self.pipeline_ptr).model).feature_count };
+        for index in 0..feature_count {
+            let mut col = self.input_col(index)?;
+            for row in 0..rows.min(self.nrow) {
+                match col.data_type {
+                    MOJO_DataType::MOJO_BOOL => col.write_next::<bool>(None)?,
+                    MOJO_DataType::MOJO_FLOAT => col.write_next::<f32>(None)?,
+                    MOJO_DataType::MOJO_DOUBLE => col.write_next::<f64>(None)?,
+                    MOJO_DataType::MOJO_INT32 => col.write_next::<i32>(None)?,
+                    MOJO_DataType::MOJO_INT64 => col.write_next::<i64>(None)?,
+                    MOJO_DataType::MOJO_STRING => col.write_str(row, None)?,
+                    MOJO_DataType::MOJO_UNKNOWN => break,
+                }
             }
         }
+        Ok(())
     }
 
     pub fn input_f32_mut(&mut self, feature_index: usize) -> error::Result<&mut [f32]> {
         unsafe {
             let data = self.input_data(feature_index)
                 .ok_or(error::MojoError::InvalidInputIndex(feature_index))?;
-            Ok(std::slice::from_raw_parts_mut(transmute(data), self.nrow))
+            Ok(std::slice::from_raw_parts_mut(transmute::<*mut u8, *mut f32>(data), self.nrow))
         }
     }
 
@@ -386,7 +663,7 @@ impl<'a> RawFrame<'a> {
         unsafe {
             let data = self.output_data(output_index)
                 .ok_or(error::MojoError::InvalidOutputIndex(output_index))?;
-            Ok(std::slice::from_raw_parts(transmute(data), self.nrow))
+            Ok(std::slice::from_raw_parts(transmute::<*const u8, *const f32>(data), self.nrow))
         }
     }
 }
@@ -398,20 +675,22 @@ impl<'a> Drop for RawFrame<'a> {
 }
 
 pub struct RawColumnBuffer<'a> {
-    lib: &'a DaiMojoLibrary,
+    /// Present for string columns only
+    strings: Option<&'a DaiMojoStringBindings>,
     pub data_type: MOJO_DataType,
     array_start: *const u8,
     current: *mut u8,
 }
 
 impl<'a> RawColumnBuffer<'a> {
-    fn new(lib: &'a DaiMojoLibrary, data_type: MOJO_DataType, ptr: *const u8) -> Self {
-        Self {
-            lib,
+    fn new(lib: &'a DaiMojoLibrary, data_type: MOJO_DataType, ptr: *const u8) -> error::Result<Self> {
+        lib.check_column_type(data_type)?;
+        Ok(Self {
+            strings: if data_type == MOJO_DataType::MOJO_STRING { lib.strings() } else { None },
             data_type,
             array_start: ptr,
             current: ptr as *mut u8,
-        }
+        })
     }
 
     pub fn reset_current(vec: &mut Vec<Self>) {
@@ -436,16 +715,64 @@ impl<'a> RawColumnBuffer<'a> {
         }
     }
 
+    fn check_type(&self, expected: MOJO_DataType) -> error::Result<()> {
+        if self.data_type != expected {
+            return Err(MojoError::ColumnTypeMismatch { expected, found: self.data_type });
+        }
+        Ok(())
+    }
+
+    /// Read next value, with NA as `None`.
+    pub fn read_next<T: ColumnValue>(&mut self) -> error::Result<Option<T>> {
+        self.check_type(T::DATA_TYPE)?;
+        Ok(T::read_from(self))
+    }
+
+    /// Write next value, with `None` as NA.
+    pub fn write_next<T: ColumnValue>(&mut self, value: Option<T>) -> error::Result<()> {
+        self.check_type(T::DATA_TYPE)?;
+        T::write_to(value, self);
+        Ok(())
+    }
+
+    /// Read string at given row, with empty string as `None`.
+    pub fn read_string(&mut self, row: usize) -> error::Result<Option<String>> {
+        self.check_type(MOJO_DataType::MOJO_STRING)?;
+        let value = self.unchecked_read_string(row);
+        Ok(if value.is_empty() { None } else { Some(value.into_owned()) })
+    }
+
+    /// Write string at given row, with `None` as empty string.
+    pub fn write_str(&mut self, row: usize, value: Option<&str>) -> error::Result<()> {
+        self.check_type(MOJO_DataType::MOJO_STRING)?;
+        self.unchecked_write_str(row, value.unwrap_or(""));
+        Ok(())
+    }
+
     pub fn unchecked_write_str(&mut self, row: usize, value: &str) {
+        let Some(strings) = self.strings else { return };
         unsafe {
             let value = CString::from_vec_unchecked(value.as_bytes().to_vec());
-            self.lib.api.MOJO_Column_Write_Str(self.array_start as *mut u8, row, value.as_ptr());
+            strings.MOJO_Column_Write_Str(self.array_start as *mut u8, row, value.as_ptr());
+        }
+    }
+
+    /// Write string bytes at given row, terminated in `scratch`, which is reused across calls to avoid allocation.
+    /// Like with [RawColumnBuffer::unchecked_write_str], the library ignores anything after an embedded NUL.
+    pub fn unchecked_write_bytes(&mut self, row: usize, value: &[u8], scratch: &mut Vec<u8>) {
+        let Some(strings) = self.strings else { return };
+        scratch.clear();
+        scratch.extend_from_slice(value);
+        scratch.push(0);
+        unsafe {
+            strings.MOJO_Column_Write_Str(self.array_start as *mut u8, row, scratch.as_ptr() as *const c_char);
         }
     }
 
-    pub fn unchecked_read_string(&mut self, row: usize) -> Cow<str> {
+    pub fn unchecked_read_string(&mut self, row: usize) -> Cow<'_, str> {
+        let Some(strings) = self.strings else { return Cow::Borrowed("") };
         unsafe {
-            let value = self.lib.api.MOJO_Column_Read_Str(self.array_start as *mut u8, row);
+            let value = strings.MOJO_Column_Read_Str(self.array_start as *mut u8, row);
             CStr::from_ptr(value).to_string_lossy()
         }
     }
@@ -454,12 +781,14 @@ impl<'a> RawColumnBuffer<'a> {
 #[cfg(test)]
 mod tests {
     use std::borrow::Cow;
-    use std::ffi::CStr;
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
     use std::path::Path;
 
     use crate::daimojo_library::{MOJO_DataType, MOJO_Transform_Ops, RawPipeline};
+    use crate::MojoError;
 
-    use super::{DaiMojoLibrary, RawModel};
+    use super::{DaiMojoLibrary, MOJO_INT32_NAN, RawColumnBuffer, RawFrame, RawModel, check_model_file, check_tf_lib_prefix, path_to_cstring};
 
     // const LIBDAIMOJO_SO: &str = "/home/pk/h2o/mojo2/cpp/build/libdaimojo.so";
     const LIBDAIMOJO_SO: &str = "libdaimojo.so";
@@ -493,4 +822,136 @@ mod tests {
         }
         //
     }
+
+    #[test]
+    fn non_utf8_path() {
+        let path = Path::new(OsStr::from_bytes(b"/tmp/model-\xff.mojo"));
+        assert_eq!(b"/tmp/model-\xff.mojo", path_to_cstring(path).unwrap().as_bytes());
+        let path = Path::new(OsStr::from_bytes(b"/tmp/model-\0.mojo"));
+        assert!(matches!(path_to_cstring(path), Err(MojoError::InvalidPath { .. })));
+    }
+
+    #[test]
+    fn missing_library() {
+        let result = DaiMojoLibrary::load("no/such/libdaimojo.so");
+        assert!(matches!(result, Err(MojoError::InvalidPath { path, .. }) if path == Path::new("no/such/libdaimojo.so")));
+    }
+
+    #[test]
+    fn model_file_checks() {
+        assert!(check_model_file(Path::new("data/iris/pipeline.mojo")).is_ok());
+        assert!(matches!(check_model_file(Path::new("data/iris/missing.mojo")), Err(MojoError::InvalidPath { .. })));
+        let dir = crate::test_support::TempDir::new("model-file-checks");
+        let small = dir.path().join("small.mojo");
+        std::fs::write(&small, b"PK").unwrap();
+        assert!(matches!(check_model_file(&small), Err(MojoError::CorruptModel { .. })));
+        let csv = dir.path().join("not-zip.mojo");
+        std::fs::write(&csv, "a,b,c\n1,2,3\n4,5,6\n7,8,9\n").unwrap();
+        assert!(matches!(check_model_file(&csv), Err(MojoError::CorruptModel { reason, .. }) if reason == "not a zip archive"));
+    }
+
+    #[test]
+    fn tf_lib_prefixes() {
+        assert!(check_tf_lib_prefix("").is_ok());
+        assert!(check_tf_lib_prefix(".").is_ok());
+        assert!(check_tf_lib_prefix("lib/linux_x64/libtf").is_ok());
+        assert!(matches!(check_tf_lib_prefix("/no/such/dir/libtf"), Err(MojoError::UnsupportedTfPrefix(_))));
+    }
+
+    #[test]
+    fn capabilities_of_fake_runtime() {
+        let lib = DaiMojoLibrary::load(crate::test_support::fake_library("libempty")).unwrap();
+        let capabilities = lib.capabilities();
+        assert!(capabilities.has("MOJO_Transform"));
+        assert!(capabilities.string_columns());
+        assert_eq!(0, capabilities.missing().count());
+        assert!(!capabilities.has("MOJO_NoSuchFunction"));
+    }
+
+    #[test]
+    fn two_runtimes_side_by_side() {
+        let dir = crate::test_support::TempDir::new("side-by-side");
+        let fake = std::fs::read(crate::test_support::fake_library("libempty")).unwrap();
+        // separate copies, so that no other test shares their instances; the second one reports another version
+        let path_a = dir.path().join("a").join("libempty.so");
+        let path_b = dir.path().join("b").join("libempty.so");
+        std::fs::create_dir_all(path_a.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(path_b.parent().unwrap()).unwrap();
+        std::fs::write(&path_a, &fake).unwrap();
+        let version = fake.windows(13).position(|bytes| bytes == b"2.99.99 EMPTY").unwrap();
+        let mut fake_b = fake;
+        fake_b[version..version + 13].copy_from_slice(b"2.99.98 EMPTY");
+        std::fs::write(&path_b, &fake_b).unwrap();
+        crate::test_support::license_fake_runtime(&path_a);
+        crate::test_support::license_fake_runtime(&path_b);
+
+        let lib_a = DaiMojoLibrary::load(&path_a).unwrap();
+        let lib_b = DaiMojoLibrary::load(&path_b).unwrap();
+        assert_eq!("2.99.99 EMPTY", lib_a.version());
+        assert_eq!("2.99.98 EMPTY", lib_b.version());
+
+        // each library counts its own models in their creation time
+        let created = |model: &RawModel| model.time_created_utc().timestamp();
+        let first_a = RawModel::load(&lib_a, "data/iris/pipeline.mojo", "").unwrap();
+        let first_b = RawModel::load(&lib_b, "data/iris/pipeline.mojo", "").unwrap();
+        let model_b = RawModel::load(&lib_b, "data/iris/pipeline.mojo", "").unwrap();
+        let model_a = RawModel::load(&lib_a, "data/iris/pipeline.mojo", "").unwrap();
+        assert_eq!([1, 2], [created(&first_a), created(&model_a)]);
+        assert_eq!([1, 2], [created(&first_b), created(&model_b)]);
+
+        let pipeline_a = RawPipeline::new(&model_a, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let pipeline_b = RawPipeline::new(&model_b, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let frame_a = RawFrame::new(&pipeline_a, 1).unwrap();
+        let frame_b = RawFrame::new(&pipeline_b, 1).unwrap();
+        for _ in 0..3 {
+            pipeline_a.transform(&frame_a, 1, false).unwrap();
+            pipeline_b.transform(&frame_b, 1, false).unwrap();
+        }
+        assert_eq!("2.99.99 EMPTY", lib_a.version());
+        assert_eq!("2.99.98 EMPTY", lib_b.version());
+    }
+
+    #[test]
+    fn column_values_with_na() {
+        let lib = DaiMojoLibrary::load(crate::test_support::fake_library("libempty")).unwrap();
+        let mut ints = [0i32; 3];
+        let mut col = RawColumnBuffer::new(&lib, MOJO_DataType::MOJO_INT32, ints.as_mut_ptr().cast()).unwrap();
+        col.write_next(Some(7)).unwrap();
+        col.write_next::<i32>(None).unwrap();
+        col.write_next(Some(MOJO_INT32_NAN - 2)).unwrap();
+        assert_eq!([7, MOJO_INT32_NAN, MOJO_INT32_NAN - 2], ints);
+        let mut col = RawColumnBuffer::new(&lib, MOJO_DataType::MOJO_INT32, ints.as_ptr().cast()).unwrap();
+        assert_eq!(Some(7), col.read_next::<i32>().unwrap());
+        assert_eq!(None, col.read_next::<i32>().unwrap());
+        assert_eq!(Some(MOJO_INT32_NAN - 2), col.read_next::<i32>().unwrap());
+        assert!(matches!(col.read_next::<i64>(), Err(MojoError::ColumnTypeMismatch { .. })));
+
+        let doubles = [1.5, f64::NAN];
+        let mut col = RawColumnBuffer::new(&lib, MOJO_DataType::MOJO_DOUBLE, doubles.as_ptr().cast()).unwrap();
+        assert_eq!(Some(1.5), col.read_next::<f64>().unwrap());
+        assert_eq!(None, col.read_next::<f64>().unwrap());
+
+        let mut bools = [0i8, 1, 2, -1];
+        let mut col = RawColumnBuffer::new(&lib, MOJO_DataType::MOJO_BOOL, bools.as_ptr().cast()).unwrap();
+        assert_eq!(Some(false), col.read_next::<bool>().unwrap());
+        assert_eq!(Some(true), col.read_next::<bool>().unwrap());
+        assert_eq!(Some(true), col.read_next::<bool>().unwrap(), "any non-zero byte");
+        assert_eq!(Some(true), col.read_next::<bool>().unwrap(), "any non-zero byte");
+        let mut col = RawColumnBuffer::new(&lib, MOJO_DataType::MOJO_BOOL, bools.as_mut_ptr().cast()).unwrap();
+        col.write_next(Some(true)).unwrap();
+        col.write_next::<bool>(None).unwrap();
+        col.write_next(Some(false)).unwrap();
+        assert_eq!([1, 0, 0, -1], bools, "NA is written as false");
+    }
+
+    #[test]
+    fn accepted_versions() {
+        let lib = DaiMojoLibrary::load_accepting(crate::test_support::fake_library("libempty"), &">=2.99".parse().unwrap()).unwrap();
+        assert_eq!(Some("EMPTY"), lib.runtime_version().suffix.as_deref());
+        assert_eq!(Some("EMPTY"), lib.compatibility().suffix);
+        let result = DaiMojoLibrary::load_accepting(crate::test_support::fake_library("libempty"), &"<2.99".parse().unwrap());
+        assert!(matches!(result, Err(MojoError::UnsupportedApi(_, version)) if version == "2.99.99 EMPTY"));
+        let result = DaiMojoLibrary::load(crate::test_support::fake_library("libjustversion"));
+        assert!(matches!(result, Err(MojoError::UnsupportedApi(_, version)) if version == "JUST VERSION"));
+    }
 }
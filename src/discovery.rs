@@ -0,0 +1,81 @@
+//! Searching for the daimojo library
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+/// Environment variable with path to the daimojo library.
+pub const DAIMOJO_LIB_VAR: &str = "DAIMOJO_LIB";
+/// File name of the daimojo library.
+pub const LIBDAIMOJO_SO: &str = "libdaimojo.so";
+/// Location of the library in the project layout.
+const PROJECT_LIB_DIR: &str = "lib/linux_x64";
+
+/// Candidate locations of the library, in the order they should be tried:
+/// * the explicit path, if given - then it is the only candidate
+/// * the path in [DAIMOJO_LIB_VAR]
+/// * next to the executable, and in its `lib/linux_x64/` subdirectory
+/// * next to the mojo file
+/// * `lib/linux_x64/` under current directory
+/// * bare file name, resolved by the system loader (`LD_LIBRARY_PATH` etc.)
+pub fn candidates(explicit: Option<&Path>, mojo: Option<&Path>) -> Vec<PathBuf> {
+    candidates_with(explicit, std::env::var_os(DAIMOJO_LIB_VAR), mojo)
+}
+
+/// Same as [candidates], with the value of [DAIMOJO_LIB_VAR] passed in.
+fn candidates_with(explicit: Option<&Path>, lib_var: Option<OsString>, mojo: Option<&Path>) -> Vec<PathBuf> {
+    if let Some(explicit) = explicit {
+        return vec![explicit.to_path_buf()];
+    }
+    let mut candidates = Vec::new();
+    if let Some(path) = lib_var {
+        candidates.push(PathBuf::from(path));
+    }
+    if let Some(exe_dir) = std::env::current_exe().ok().as_deref().and_then(Path::parent) {
+        candidates.push(exe_dir.join(LIBDAIMOJO_SO));
+        candidates.push(exe_dir.join(PROJECT_LIB_DIR).join(LIBDAIMOJO_SO));
+    }
+    if let Some(mojo_dir) = mojo.and_then(Path::parent) {
+        let mojo_dir = if mojo_dir.as_os_str().is_empty() { Path::new(".") } else { mojo_dir };
+        candidates.push(mojo_dir.join(LIBDAIMOJO_SO));
+    }
+    candidates.push(Path::new(PROJECT_LIB_DIR).join(LIBDAIMOJO_SO));
+    candidates.push(PathBuf::from(LIBDAIMOJO_SO));
+    let mut seen = std::collections::HashSet::new();
+    candidates.retain(|path| seen.insert(path.clone()));
+    candidates
+}
+
+/// Bare file name without any directory is passed to the system loader, which searches its own paths.
+pub(crate) fn is_system_lookup(path: &Path) -> bool {
+    path.parent().is_none_or(|parent| parent.as_os_str().is_empty()) && !path.exists()
+}
+
+#[cfg(test)]
+mod tests {
+use std::path::{Path, PathBuf};
+    use super::{candidates_with, is_system_lookup};
+
+    #[test]
+    fn candidate_order() {
+        let lib_var = Some("/opt/daimojo/libdaimojo.so".into());
+        assert_eq!(vec![PathBuf::from("my/lib.so")], candidates_with(Some(Path::new("my/lib.so")), lib_var.clone(), None));
+
+        let found = candidates_with(None, lib_var, Some(Path::new("data/iris/pipeline.mojo")));
+        assert_eq!(PathBuf::from("/opt/daimojo/libdaimojo.so"), found[0]);
+        let mojo_index = found.iter().position(|p| p == Path::new("data/iris/libdaimojo.so")).unwrap();
+        let project_index = found.iter().position(|p| p == Path::new("lib/linux_x64/libdaimojo.so")).unwrap();
+        assert!(mojo_index < project_index);
+        assert_eq!(Some(&PathBuf::from("libdaimojo.so")), found.last());
+
+        let found = candidates_with(None, None, Some(Path::new("pipeline.mojo")));
+        assert!(found.contains(&PathBuf::from("./libdaimojo.so")));
+        assert_eq!(Some(&PathBuf::from("./libdaimojo.so")), found.get(2));
+    }
+
+    #[test]
+    fn system_lookup() {
+        assert!(is_system_lookup(Path::new("libdaimojo.so")));
+        assert!(!is_system_lookup(Path::new("lib/linux_x64/libdaimojo.so")));
+        assert!(!is_system_lookup(Path::new("Cargo.toml")));
+    }
+}
@@ -1,5 +1,7 @@
 use std::ffi::NulError;
+use std::path::PathBuf;
 use thiserror::Error as ThisError;
+use crate::MOJO_DataType;
 
 pub type Result<T> = std::result::Result<T, MojoError>;
 
@@ -9,10 +11,38 @@ pub enum MojoError {
     IoError(#[from] std::io::Error),
     #[error("CSV Error")]
     CsvError(#[from] csv::Error),
+    #[error("Serde Error")]
+    SerdeError(#[from] serde_json::Error),
     #[error("DlOpen Error")]
     DlOpenError(#[from] dlopen2::Error),
-    #[error("Pipeline model is not valid - check your license settings")]
-    InvalidModel,
+    #[error("Cannot load library '{}'", path.display())]
+    LibraryLoadError { path: PathBuf, source: dlopen2::Error },
+    #[error("daimojo library not found, tried: {}", .0.join("; "))]
+    LibraryNotFound(Vec<String>),
+    #[error("Invalid path '{}'", path.display())]
+    InvalidPath { path: PathBuf, source: std::io::Error },
+    #[error("Pipeline model '{}' is not valid - check your license settings", .0.display())]
+    InvalidModel(PathBuf),
+    #[error("Model file not found: '{}'", .0.display())]
+    ModelNotFound(PathBuf),
+    #[error("Model file '{}' is corrupt: {reason}", path.display())]
+    CorruptModel { path: PathBuf, reason: String },
+    #[error("The daimojo library failed to load model '{}'", .0.display())]
+    ModelLoadFailed(PathBuf),
+    #[error("Model '{}' has a different schema: {reason}", path.display())]
+    SchemaMismatch { path: PathBuf, reason: String },
+    #[error("Model '{0}' is already registered")]
+    DuplicateModel(String),
+    #[error("No registered model matches '{0}'")]
+    ModelNotRegistered(String),
+    #[error("Version '{0}' is reserved for routing and cannot be registered")]
+    ReservedVersion(String),
+    #[error("Model name '{0}' cannot contain '@', which separates the version in routing")]
+    InvalidModelName(String),
+    #[error("Model '{0}' has more than one default version")]
+    DuplicateDefault(String),
+    #[error("Unsupported TensorFlow library prefix '{0}'")]
+    UnsupportedTfPrefix(String),
     #[error("Cannot create pipeline")]
     InvalidPipeline,
     #[error("Null Error")]
@@ -24,5 +54,13 @@ pub enum MojoError {
     #[error("invalid index of output column: {0}")]
     InvalidOutputIndex(usize),
     #[error("{0}: Not a supported API inside version '{1}'")]
-    UnsupportedApi(String, String)
+    UnsupportedApi(String, String),
+    #[error("Column of type {found:?} accessed as {expected:?}")]
+    ColumnTypeMismatch { expected: MOJO_DataType, found: MOJO_DataType },
+    #[error("Unsupported type of column '{0}'")]
+    UnsupportedColumnType(String),
+    #[error("Invalid row {0}: {1}")]
+    InvalidRow(usize, String),
+    #[error("Line {line}: missing value of column '{column}'")]
+    MissingValue { line: u64, column: String },
 }
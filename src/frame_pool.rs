@@ -0,0 +1,176 @@
+//! Pool of frames, reused across requests to avoid native allocation for each of them
+//!
+//! Frames are keyed by pipeline and capacity; requested row counts are rounded up to a power of two,
+//! so that requests of similar size share frames. A reused frame gets only the requested rows cleared,
+//! so that small requests served by large frames stay cheap.
+//! The runtime does not document that frames or pipelines can be used by several threads,
+//! so the pool and its frames stay on the thread that created them; threads scoring in parallel need a pool each.
+
+use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
+use std::ops::Deref;
+use crate::daimojo_library::{RawFrame, RawPipeline};
+use crate::error;
+
+/// Smallest capacity of pooled frames.
+pub const MIN_CAPACITY: usize = 16;
+/// Default number of idle frames kept for each pipeline and capacity.
+pub const DEFAULT_MAX_IDLE: usize = 4;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PoolStats {
+    /// Requests served by an idle frame
+    pub hits: u64,
+    /// Requests that allocated a new frame
+    pub misses: u64,
+    /// Frames dropped on return, because the pool had enough idle ones
+    pub discarded: u64,
+    /// Idle frames dropped, because their inputs could not be cleared
+    pub clear_failed: u64,
+}
+
+impl PoolStats {
+    /// Ratio of requests served without allocation, between 0 and 1.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+pub struct FramePool<'a> {
+    state: RefCell<PoolState<'a>>,
+    max_idle: usize,
+}
+
+#[derive(Default)]
+struct PoolState<'a> {
+    idle: HashMap<(usize, usize), Vec<RawFrame<'a>>>,
+    stats: PoolStats,
+}
+
+impl<'a> Default for FramePool<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> FramePool<'a> {
+    pub fn new() -> Self {
+        Self::with_max_idle(DEFAULT_MAX_IDLE)
+    }
+
+    pub fn with_max_idle(max_idle: usize) -> Self {
+        Self { state: RefCell::new(PoolState::default()), max_idle }
+    }
+
+    /// Get a frame with room for at least `rows` rows, where the first `rows` rows have all inputs missing.
+    /// It returns to the pool when dropped.
+    pub fn get<'p>(&'p self, pipeline: &'a RawPipeline, rows: usize) -> error::Result<PooledFrame<'p, 'a>> {
+        let key = (pipeline.id(), capacity_for(rows));
+        loop {
+            let idle = self.state().idle.get_mut(&key).and_then(Vec::pop);
+            let Some(frame) = idle else { break };
+            match frame.clear_input_rows(rows) {
+                Ok(()) => {
+                    self.state().stats.hits += 1;
+                    return Ok(PooledFrame { pool: self, key, frame: Some(frame) });
+                }
+                Err(e) => {
+                    log::warn!("Discarding frame that cannot be cleared: {e}");
+                    self.state().stats.clear_failed += 1;
+                }
+            }
+        }
+        self.state().stats.misses += 1;
+        let frame = RawFrame::new(pipeline, key.1)?;
+        Ok(PooledFrame { pool: self, key, frame: Some(frame) })
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.state().stats
+    }
+
+    /// Number of frames waiting in the pool.
+    pub fn idle_count(&self) -> usize {
+        self.state().idle.values().map(Vec::len).sum()
+    }
+
+    fn put(&self, key: (usize, usize), frame: RawFrame<'a>) {
+        let mut state = self.state();
+        let frames = state.idle.entry(key).or_default();
+        if frames.len() < self.max_idle {
+            frames.push(frame);
+        } else {
+            state.stats.discarded += 1;
+        }
+    }
+
+    fn state(&self) -> RefMut<'_, PoolState<'a>> {
+        self.state.borrow_mut()
+    }
+}
+
+/// Frame borrowed from [FramePool].
+pub struct PooledFrame<'p, 'a> {
+    pool: &'p FramePool<'a>,
+    key: (usize, usize),
+    frame: Option<RawFrame<'a>>,
+}
+
+impl<'p, 'a> Deref for PooledFrame<'p, 'a> {
+    type Target = RawFrame<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.frame.as_ref().expect("present until dropped")
+    }
+}
+
+impl<'p, 'a> Drop for PooledFrame<'p, 'a> {
+    fn drop(&mut self) {
+        if let Some(frame) = self.frame.take() {
+            self.pool.put(self.key, frame);
+        }
+    }
+}
+
+fn capacity_for(rows: usize) -> usize {
+    rows.max(MIN_CAPACITY).next_power_of_two()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{DaiMojoLibrary, MOJO_Transform_Ops, RawModel, RawPipeline};
+    use super::{capacity_for, FramePool};
+
+    #[test]
+    fn capacities() {
+        assert_eq!(16, capacity_for(0));
+        assert_eq!(16, capacity_for(16));
+        assert_eq!(32, capacity_for(17));
+        assert_eq!(1024, capacity_for(1000));
+    }
+
+    #[test]
+    fn reuse() {
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, "data/iris/pipeline.mojo", "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let pool = FramePool::with_max_idle(1);
+        {
+            let a = pool.get(&pipeline, 10).unwrap();
+            let b = pool.get(&pipeline, 10).unwrap();
+            assert_eq!(16, a.nrow);
+            pipeline.transform(&b, 10, false).unwrap();
+        }
+        assert_eq!(1, pool.idle_count());
+        let c = pool.get(&pipeline, 3).unwrap();
+        let d = pool.get(&pipeline, 100).unwrap();
+        assert_eq!(128, d.nrow);
+        drop((c, d));
+        let stats = pool.stats();
+        assert_eq!((1, 3, 1, 0), (stats.hits, stats.misses, stats.discarded, stats.clear_failed));
+        assert_eq!(0.25, stats.hit_rate());
+    }
+}
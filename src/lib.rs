@@ -1,16 +1,41 @@
 //! Convenient abstraction for daimojo interface
 
-pub use csv_export::FrameExporter;
+pub use batch_sizer::BatchSizer;
+pub use bool_format::BoolFormat;
+pub use csv_export::{CsvFormat, FlushPolicy, FrameExporter};
 pub use csv_import::FrameImporter;
-pub use daimojo_library::{DaiMojoLibrary, MOJO_DataType, MOJO_INT32_NAN, MOJO_INT64_NAN, MOJO_Transform_Ops};
+pub use daimojo_library::{ColumnValue, DaiMojoLibrary, MOJO_DataType, MOJO_INT32_NAN, MOJO_INT64_NAN, MOJO_Transform_Ops};
 pub use daimojo_library::{RawFrame, RawModel, RawPipeline};
 pub use error::{MojoError, Result};
+pub use frame_pool::FramePool;
+pub use license::License;
+pub use number_format::NumberFormat;
+pub use model_manager::ModelManager;
+pub use registry::Registry;
+pub use scorer::Scorer;
 
+pub mod batch_sizer;
+pub mod bool_format;
+pub mod codegen;
+pub mod compression;
 mod daimojo_library;
+pub mod discovery;
 mod carray;
 mod csv_import;
 mod csv_export;
 mod error;
+pub mod frame_pool;
+pub mod license;
+pub mod model_manager;
+pub mod number_format;
+pub mod pipelined;
+pub mod registry;
+mod scorer;
+pub mod shadow;
+pub mod version;
+#[cfg(any(test, feature = "test-support"))]
+#[doc(hidden)]
+pub mod test_support;
 
 #[cfg(test)]
 mod tests {
@@ -23,6 +48,7 @@ mod tests {
     const LIBDAIMOJO_SO: &str = "libdaimojo.so";
 
     #[test]
+    #[allow(clippy::excessive_precision)]
     fn simple_iris_test() -> error::Result<()>{
         let lib = DaiMojoLibrary::load(Path::new(LIBDAIMOJO_SO))?;
         let version = lib.version();
@@ -38,7 +64,7 @@ mod tests {
         frame.input_f32_mut(2, /*"petal_len"*/)?[0] = 1.4;
         frame.input_f32_mut(3, /*"petal_wid"*/)?[0] = 0.2;
         log::trace!("ncol before predict: {}", frame.ncol());
-        pipeline.transform(&mut frame, 1, false)?;
+        pipeline.transform(&frame, 1, false)?;
         log::trace!("ncol after predict: {}", frame.ncol());
         // present output columns
         let setosa     = frame.output_f32(0)?[0];
@@ -71,7 +97,7 @@ mod tests {
         fixed_acidity[4] = 7.3;
         // fixed_acidity.copy_from_slice(&[11.8, 7.2, 6.4, 8.6, 7.3]); // alternative way to fill it
         log::trace!("ncol before predict: {}", frame.ncol());
-        pipeline.transform(&mut frame, 5, true).unwrap();
+        pipeline.transform(&frame, 5, true).unwrap();
         log::trace!("ncol after predict: {}", frame.ncol());
         // present output columns
         let q3 = frame.output_f32(0/*"quality.3"*/).unwrap();
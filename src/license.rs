@@ -0,0 +1,118 @@
+//! Locating the Driverless AI license
+//!
+//! The daimojo library reads the license from environment variables only,
+//! so a license found elsewhere must be exposed to it by setting these variables before the model is loaded.
+//! The library does not touch the environment itself, as that is unsound while other threads may read it;
+//! applications apply [License::env_vars] at startup instead.
+
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use crate::{error, MojoError};
+
+/// Environment variable with path to the license file.
+pub const LICENSE_FILE_VAR: &str = "DRIVERLESS_AI_LICENSE_FILE";
+/// Environment variable with the license key itself.
+pub const LICENSE_KEY_VAR: &str = "DRIVERLESS_AI_LICENSE_KEY";
+/// All environment variables where the daimojo library looks for the license.
+pub const LICENSE_ENV_VARS: &[&str] = &[LICENSE_FILE_VAR, LICENSE_KEY_VAR];
+
+/// Where the license was found.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LicenseOrigin {
+    /// Passed explicitly by the caller
+    Explicit,
+    /// Taken from one of [LICENSE_ENV_VARS]
+    Environment,
+    /// Found at one of [default_locations]
+    DefaultLocation,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LicenseData {
+    File(PathBuf),
+    Key(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct License {
+    pub origin: LicenseOrigin,
+    pub data: LicenseData,
+}
+
+impl License {
+    /// Find the license, in this order: explicit key, explicit file, environment variables, default locations.
+    /// Explicitly given file must exist.
+    pub fn locate(file: Option<&Path>, key: Option<&str>) -> error::Result<Option<License>> {
+        if let Some(key) = key {
+            return Ok(Some(Self { origin: LicenseOrigin::Explicit, data: LicenseData::Key(key.to_string()) }));
+        }
+        if let Some(file) = file {
+            if !file.is_file() {
+                return Err(MojoError::InvalidPath {
+                    path: file.to_path_buf(),
+                    source: std::io::Error::new(std::io::ErrorKind::NotFound, "license file not found"),
+                });
+            }
+            return Ok(Some(Self { origin: LicenseOrigin::Explicit, data: LicenseData::File(file.to_path_buf()) }));
+        }
+        if let Some(key) = std::env::var_os(LICENSE_KEY_VAR) {
+            return Ok(Some(Self { origin: LicenseOrigin::Environment, data: LicenseData::Key(key.to_string_lossy().to_string()) }));
+        }
+        if let Some(file) = std::env::var_os(LICENSE_FILE_VAR) {
+            let file = PathBuf::from(file);
+            if file.is_file() {
+                return Ok(Some(Self { origin: LicenseOrigin::Environment, data: LicenseData::File(file) }));
+            }
+            log::warn!("{LICENSE_FILE_VAR} points to a missing file: {}", file.display());
+        }
+        Ok(default_locations().into_iter()
+            .find(|file| file.is_file())
+            .map(|file| Self { origin: LicenseOrigin::DefaultLocation, data: LicenseData::File(file) }))
+    }
+
+    /// Environment changes that make the license visible to the daimojo library, and only this license:
+    /// variables to set to the value, or to remove for `None`. Nothing changes for a license taken from the environment.
+    pub fn env_vars(&self) -> Vec<(&'static str, Option<OsString>)> {
+        if self.origin == LicenseOrigin::Environment {
+            return Vec::new();
+        }
+        match &self.data {
+            LicenseData::File(file) => vec![(LICENSE_FILE_VAR, Some(file.into())), (LICENSE_KEY_VAR, None)],
+            LicenseData::Key(key) => vec![(LICENSE_KEY_VAR, Some(key.into())), (LICENSE_FILE_VAR, None)],
+        }
+    }
+}
+
+impl Display for License {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let origin = match self.origin {
+            LicenseOrigin::Explicit => "explicit option",
+            LicenseOrigin::Environment => "environment",
+            LicenseOrigin::DefaultLocation => "default location",
+        };
+        match &self.data {
+            LicenseData::File(file) => write!(f, "file '{}' ({origin})", file.display()),
+            LicenseData::Key(key) => write!(f, "key of {} characters ({origin})", key.len()),
+        }
+    }
+}
+
+/// Standard locations of the license file, in order of preference.
+pub fn default_locations() -> Vec<PathBuf> {
+    let mut locations = Vec::new();
+    if let Some(home) = std::env::var_os("HOME") {
+        locations.push(Path::new(&home).join(".driverlessai/license.sig"));
+    }
+    locations.push(PathBuf::from("license.sig"));
+    locations.push(PathBuf::from("/opt/h2oai/dai/home/.driverlessai/license.sig"));
+    locations
+}
+
+/// Names of license environment variables that are set.
+pub(crate) fn license_env_vars() -> Vec<&'static str> {
+    LICENSE_ENV_VARS.iter()
+        .copied()
+        .filter(|name| std::env::var_os(name).is_some())
+        .collect()
+}
@@ -2,10 +2,17 @@ extern crate core;
 
 use std::borrow::Cow;
 use std::ffi::CStr;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::time::Duration;
 use clap::{ArgAction, Parser, Subcommand};
+use clap::builder::RangedU64ValueParser;
 use log::LevelFilter;
-use daimojo::{DaiMojoLibrary, MOJO_Transform_Ops, RawModel, RawPipeline, MOJO_DataType};
+use daimojo::{BoolFormat, CsvFormat, DaiMojoLibrary, FlushPolicy, NumberFormat, MOJO_Transform_Ops, RawModel, RawPipeline, MOJO_DataType, License};
+use daimojo::license;
+use daimojo::compression::Compression;
+use daimojo::number_format::Notation;
+use daimojo::version::VersionRange;
 
 /// CLI for daimojo libraries
 #[derive(Parser)]
@@ -18,32 +25,190 @@ struct Cli {
     #[arg(short, long, action = ArgAction::Count)]
     silent: u8,
 
-    /// Path to the daimojo library
-    #[arg(long,default_value="libdaimojo.so")]
-    lib: String,
+    /// Path to the daimojo library; if not specified, it is searched in $DAIMOJO_LIB,
+    /// next to the executable, next to the pipeline, in lib/linux_x64/ and on the system loader path
+    #[arg(long)]
+    lib: Option<String>,
+
+    /// Accepted versions of the daimojo library, like `>=2.4, <3`
+    #[arg(long,value_name="RANGE",default_value=daimojo::version::DEFAULT_RANGE)]
+    runtime_version: VersionRange,
 
     /// Path to the pipeline
     #[arg(long,value_name="PIPELINE",default_value="pipeline.mojo")]
     mojo: String,
 
+    /// Path to the Driverless AI license file
+    #[arg(long)]
+    license_file: Option<String>,
+
+    /// Driverless AI license key
+    #[arg(long)]
+    license_key: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
+// parsed once, so the size of the predict variant does not matter
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Show some data about the pipeline
     Show,
     /// Run prediction
     Predict {
-        /// Set batch size. For 0, it is determined automatically
+        /// Set batch size. For 0, it is estimated from the input and adapted to measured scoring time
         #[arg(long="batch",default_value="0")]
         batch_size: usize,
+        /// Limit of frame memory when the batch size is automatic, like `512M` or `2G`
+        #[arg(long,value_name="SIZE",default_value=batch_sizing::DEFAULT_MAX_MEMORY,value_parser=batch_sizing::parse_memory)]
+        max_memory: u64,
+        /// Output file; stdout if not specified or `-`
         #[arg(long="out")]
         output: Option<String>,
-        //TODO later, this will probably be Vec<String>
+        /// Flush output after every row, for interactive streaming; by default, output is flushed once per batch
+        #[arg(long)]
+        line_buffered: bool,
+        /// Flush output only when this many milliseconds passed since the previous flush
+        #[arg(long,value_name="MS",conflicts_with="line_buffered")]
+        flush_interval: Option<u64>,
+        /// Compression of input: `none`, `gzip` or `zstd`; detected from the data when not given
+        #[arg(long,value_name="KIND")]
+        compression: Option<Compression>,
+        /// Compression of output: `none`, `gzip` or `zstd`; by the `.gz` or `.zst` extension when not given
+        #[arg(long,value_name="KIND")]
+        out_compression: Option<Compression>,
+        /// Parse the next batch and write the previous output on separate threads, while the current batch is scored
+        #[arg(long,conflicts_with="shadow")]
+        pipelined: bool,
+        /// Comma separated tokens read as true in boolean inputs, ignoring case; replace the defaults `true,1,1.0,yes,y,t`
+        #[arg(long,value_name="TOKENS",value_delimiter=',')]
+        bool_true: Option<Vec<String>>,
+        /// Comma separated tokens read as false in boolean inputs, ignoring case; replace the defaults `false,0,0.0,no,n,f`
+        #[arg(long,value_name="TOKENS",value_delimiter=',')]
+        bool_false: Option<Vec<String>>,
+        /// Comma separated tokens read as missing in boolean inputs, ignoring case; replace the defaults `,NA,N/A,null,NaN`.
+        /// Booleans have no NA, so missing values are read as false, like any other token, which is logged as a warning
+        #[arg(long,value_name="TOKENS",value_delimiter=',')]
+        bool_na: Option<Vec<String>>,
+        /// Rendering of boolean outputs, as `TRUE:FALSE`, like `1:0` or `yes:no`
+        #[arg(long,value_name="TRUE:FALSE",default_value="true:false",value_parser=parse_bool_output)]
+        bool_output: (String, String),
+        /// Notation of floating point outputs: `shortest`, `fixed:N` (decimals), `sig:N` (significant digits) or `sci:N`
+        #[arg(long,value_name="NOTATION",default_value="shortest")]
+        float_format: Notation,
+        /// Decimal separator of floating point outputs
+        #[arg(long,default_value=".")]
+        decimal_separator: char,
+        /// Written for missing numeric outputs, like `NA` or an empty token; by default, the stored value is written, like `NaN`
+        #[arg(long,value_name="TOKEN")]
+        na_output: Option<String>,
+        /// Candidate pipeline, scored with the same rows; its output goes to the shadow file
+        #[arg(long,value_name="PIPELINE",requires="shadow_out")]
+        shadow: Option<String>,
+        /// Output of the shadow pipeline; divergence statistics are written next to it, with `.json` extension.
+        /// Required with `--shadow`
+        #[arg(long,value_name="FILE",requires="shadow")]
+        shadow_out: Option<String>,
+        /// Write one output per input, named by this template; placeholders are `{dir}`, `{name}`, `{stem}` and `{index}`,
+        /// like `{dir}/{stem}.pred.csv`
+        #[arg(long,value_name="TEMPLATE",conflicts_with="output")]
+        out_template: Option<String>,
+        /// Input CSV files or glob patterns, possibly compressed; stdin if not specified or `-`.
+        /// Without an output template, results of all inputs are concatenated
+        inputs: Vec<String>,
+    },
+    /// Measure throughput and latency of the pipeline over a sweep of batch sizes and thread counts
+    Bench {
+        /// Number of generated rows, when no input is given
+        #[arg(long,default_value="10000")]
+        rows: usize,
+        /// Comma separated batch sizes to try
+        #[arg(long="batch",value_delimiter=',',default_value="1,10,100,1000",value_parser=RangedU64ValueParser::<usize>::new().range(1..))]
+        batch_sizes: Vec<usize>,
+        /// Comma separated thread counts to try
+        #[arg(long,value_delimiter=',',default_value="1",value_parser=RangedU64ValueParser::<usize>::new().range(1..))]
+        threads: Vec<usize>,
+        /// Comma separated import paths to try, like `records,bytes` to compare them
+        #[arg(long="importer",value_enum,value_delimiter=',',default_value="bytes")]
+        importers: Vec<cmd_bench::ImportPath>,
+        /// Write the report as JSON into this file
+        #[arg(long)]
+        json: Option<String>,
+        /// Input CSV; rows are generated from the model features if not specified
         input: Option<String>,
     },
+    /// Generate random input CSV matching the model features
+    GenInput {
+        /// Number of rows to generate
+        #[arg(long,default_value="100")]
+        rows: usize,
+        /// Seed of the random generator; same seed produces same data
+        #[arg(long,default_value="0")]
+        seed: u64,
+        /// Probability of a missing value in each cell, between 0 and 1
+        #[arg(long,default_value="0",value_parser=cmd_gen_input::parse_na_rate)]
+        na_rate: f64,
+        /// Range of values, as `MIN:MAX` for all columns or `COLUMN=MIN:MAX` for one feature; integer ranges must fit the column type
+        #[arg(long="range",value_parser=cmd_gen_input::parse_range)]
+        ranges: Vec<(Option<String>, f64, f64)>,
+        /// Output file; stdout if not specified
+        #[arg(long="out")]
+        output: Option<String>,
+    },
+    /// Score CSV files dropped into a directory, moving them to the done or failed directory when processed
+    Watch {
+        /// Directory watched for `.csv`, `.csv.gz` and `.csv.zst` files
+        #[arg(long="in",value_name="DIR")]
+        input: PathBuf,
+        /// Directory receiving the results, under the input file name
+        #[arg(long="out",value_name="DIR")]
+        output: PathBuf,
+        /// Directory receiving scored inputs with their status; `done` in the input directory if not specified
+        #[arg(long,value_name="DIR")]
+        done: Option<PathBuf>,
+        /// Directory receiving inputs that failed, with their status; `failed` in the input directory if not specified
+        #[arg(long,value_name="DIR")]
+        failed: Option<PathBuf>,
+        /// Milliseconds between scans of the input directory
+        #[arg(long,value_name="MS",default_value="1000")]
+        poll_interval: u64,
+        /// Score files present now and exit, instead of watching
+        #[arg(long)]
+        once: bool,
+        /// Set batch size. For 0, it is estimated from each input and adapted to measured scoring time
+        #[arg(long="batch",default_value="0")]
+        batch_size: usize,
+        /// Limit of frame memory when the batch size is automatic, like `512M` or `2G`
+        #[arg(long,value_name="SIZE",default_value=batch_sizing::DEFAULT_MAX_MEMORY,value_parser=batch_sizing::parse_memory)]
+        max_memory: u64,
+    },
+    /// Report where the license was found and whether the pipeline validates with it
+    LicenseCheck,
+    /// Score the input with two daimojo libraries and report differences of their outputs
+    CompareRuntimes {
+        /// Path to the first daimojo library
+        #[arg(long)]
+        lib_a: String,
+        /// Path to the second daimojo library
+        #[arg(long)]
+        lib_b: String,
+        /// Largest absolute difference of numeric values that is not reported
+        #[arg(long,default_value="0")]
+        tolerance: f64,
+        /// How to keep the two libraries apart
+        #[arg(long,value_enum,default_value="workers")]
+        isolation: cmd_compare_runtimes::Isolation,
+        /// Input CSV
+        input: String,
+    },
+    /// Generate Rust structs and a typed scorer for the pipeline
+    Codegen {
+        /// Output file; stdout if not specified
+        #[arg(long="out")]
+        output: Option<String>,
+    },
 }
 
 fn main() -> ExitCode {
@@ -67,7 +232,7 @@ fn run() -> anyhow::Result<u8> {
     let cli = Cli::parse();
 
     // setup logger
-    let level: i8 = 3 as i8 + cli.verbose as i8 - cli.silent as i8;
+    let level: i8 = 3 + cli.verbose as i8 - cli.silent as i8;
     let level = match level {
         i8::MIN..=0 => LevelFilter::Off,
         1 => LevelFilter::Error,
@@ -78,25 +243,122 @@ fn run() -> anyhow::Result<u8> {
     };
     pretty_env_logger::formatted_timed_builder()
         .format_timestamp_millis()
-        .filter_level(level.into())
+        .filter_level(level)
         .init();
+    // license
+    let license = License::locate(cli.license_file.as_deref().map(Path::new), cli.license_key.as_deref())?;
+    if let Some(license) = &license {
+        log::debug!("Using license: {license}");
+        // still single-threaded, so that nothing reads the environment meanwhile
+        for (name, value) in license.env_vars() {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+    }
     // run subcommand
     match cli.command {
         Commands::Show => {
-            let lib = load_library(&cli.lib)?;
-            return Ok(show_pipeline(&lib, &cli.mojo)?)
+            let lib = load_library(cli.lib.as_deref(), &cli.mojo, &cli.runtime_version)?;
+            show_pipeline(&lib, &cli.mojo)
+        }
+        Commands::Predict {output, out_template, inputs, batch_size, max_memory, line_buffered, flush_interval, pipelined, compression, out_compression, bool_true, bool_false, bool_na, bool_output, float_format, decimal_separator, na_output, shadow, shadow_out} => {
+            let lib = load_library(cli.lib.as_deref(), &cli.mojo, &cli.runtime_version)?;
+            let model = load_model(&lib, &cli.mojo)?;
+            let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops)?;
+            let shadow_model = shadow.map(|shadow| load_model(&lib, &shadow)).transpose()?;
+            let shadow_pipeline = shadow_model.as_ref()
+                .map(|model| RawPipeline::new(model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops))
+                .transpose()?;
+            let shadow = shadow_pipeline.as_ref().zip(shadow_out).map(|(pipeline, output)| cmd_predict::Shadow { pipeline, output });
+            let format = CsvFormat {
+                bools: BoolFormat::default()
+                    .with_tokens(bool_true, bool_false, bool_na)
+                    .with_output(&bool_output.0, &bool_output.1),
+                numbers: NumberFormat { decimal_separator, na: na_output, ..Default::default() }.with_notation(float_format),
+            };
+            let flush_policy = match (line_buffered, flush_interval) {
+                (true, _) => FlushPolicy::Line,
+                (false, Some(ms)) => FlushPolicy::Interval(Duration::from_millis(ms)),
+                (false, None) => FlushPolicy::Batch,
+            };
+            let options = cmd_predict::PredictOptions { batch_size, format, flush_policy, pipelined, max_memory, compression, out_compression };
+            Ok(cmd_predict::cmd_predict(&pipeline, output, out_template, &inputs, options, shadow)?)
+        }
+        Commands::Bench {rows, batch_sizes, threads, importers, json, input} => {
+            let lib = load_library(cli.lib.as_deref(), &cli.mojo, &cli.runtime_version)?;
+            let sweep = cmd_bench::Sweep { importers: &importers, batch_sizes: &batch_sizes, threads: &threads };
+            Ok(cmd_bench::cmd_bench(&lib, &cli.mojo, input, rows, &sweep, json)?)
         }
-        Commands::Predict {output, input, batch_size} => {
-            let lib = load_library(&cli.lib)?;
+        Commands::GenInput {rows, seed, na_rate, ranges, output} => {
+            let lib = load_library(cli.lib.as_deref(), &cli.mojo, &cli.runtime_version)?;
+            let model = load_model(&lib, &cli.mojo)?;
+            let mut generator = cmd_gen_input::InputGenerator { seed, na_rate, ..Default::default() };
+            for (column, min, max) in ranges {
+                match column {
+                    None => generator.default_range = (min, max),
+                    Some(column) => { generator.ranges.insert(column, (min, max)); }
+                }
+            }
+            Ok(cmd_gen_input::cmd_gen_input(&model, &generator, rows, output)?)
+        }
+        Commands::Watch {input, output, done, failed, poll_interval, once, batch_size, max_memory} => {
+            let lib = load_library(cli.lib.as_deref(), &cli.mojo, &cli.runtime_version)?;
+            let model = load_model(&lib, &cli.mojo)?;
+            let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops)?;
+            let dirs = cmd_watch::WatchDirs {
+                done: done.unwrap_or_else(|| input.join("done")),
+                failed: failed.unwrap_or_else(|| input.join("failed")),
+                input,
+                output,
+            };
+            let options = cmd_predict::PredictOptions {
+                batch_size,
+                format: CsvFormat::default(),
+                flush_policy: FlushPolicy::Batch,
+                pipelined: false,
+                max_memory,
+                compression: None,
+                out_compression: None,
+            };
+            Ok(cmd_watch::cmd_watch(&pipeline, &dirs, &options, Duration::from_millis(poll_interval), once)?)
+        }
+        Commands::LicenseCheck => {
+            let lib = load_library(cli.lib.as_deref(), &cli.mojo, &cli.runtime_version)?;
+            license_check(&lib, &cli.mojo, license.as_ref())
+        }
+        Commands::CompareRuntimes {lib_a, lib_b, tolerance, isolation, input} => {
+            Ok(cmd_compare_runtimes::cmd_compare_runtimes(&lib_a, &lib_b, &cli.mojo, &cli.runtime_version, &input, tolerance, isolation)?)
+        }
+        Commands::Codegen {output} => {
+            let lib = load_library(cli.lib.as_deref(), &cli.mojo, &cli.runtime_version)?;
             let model = load_model(&lib, &cli.mojo)?;
             let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops)?;
-            Ok(cmd_predict::cmd_predict(&pipeline, output, input, batch_size)?)
+            match output {
+                None => daimojo::codegen::generate(&pipeline, &mut std::io::stdout())?,
+                Some(output) => daimojo::codegen::write_file(&pipeline, output)?,
+            }
+            Ok(0)
         }
     }
 }
 
 fn show_pipeline(lib: &DaiMojoLibrary, mojo: &str) -> anyhow::Result<u8> {
     let model = load_model(lib, mojo)?;
+    let compatibility = lib.compatibility();
+    println!("* Library version: {} (bindings: {:?})", lib.runtime_version(), compatibility.bindings);
+    for quirk in compatibility.quirks {
+        println!("* Known quirk: {quirk}");
+    }
+    let capabilities = lib.capabilities();
+    let missing: Vec<&str> = capabilities.missing().collect();
+    let found = capabilities.functions.len() - missing.len();
+    if missing.is_empty() {
+        println!("* API functions: all {found} found");
+    } else {
+        println!("* API functions: {found} found, missing: {}", missing.join(", "));
+    }
     println!("* UUID: {}", model.uuid().to_string_lossy());
     println!("* Time created: {}", model.time_created_utc());
     let missing_values: Vec<Cow<str>> = model.missing_values()
@@ -118,17 +380,51 @@ fn show_pipeline(lib: &DaiMojoLibrary, mojo: &str) -> anyhow::Result<u8> {
     Ok(0)
 }
 
-fn load_library(lib: &str) -> daimojo::Result<DaiMojoLibrary> {
-    log::debug!("Loading library: '{lib}'");
-    let lib = DaiMojoLibrary::load(lib)?;
-    println!("Library's daimojo version is {}", lib.version());
+fn license_check(lib: &DaiMojoLibrary, mojo: &str, license: Option<&License>) -> anyhow::Result<u8> {
+    match license {
+        Some(license) => println!("* License: {license}"),
+        None => {
+            println!("* License: not found");
+            println!("* Searched environment variables: {}", license::LICENSE_ENV_VARS.join(", "));
+            for location in license::default_locations() {
+                println!("* Searched file: {}", location.display());
+            }
+        }
+    }
+    match load_model(lib, mojo) {
+        Ok(model) => {
+            println!("* Model '{mojo}' is valid, UUID: {}", model.uuid().to_string_lossy());
+            Ok(0)
+        }
+        Err(e) => {
+            println!("* Model '{mojo}' is not valid: {e}");
+            Ok(1)
+        }
+    }
+}
+
+fn parse_bool_output(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((t, f)) if t != f => Ok((t.to_string(), f.to_string())),
+        _ => Err(format!("expected TRUE:FALSE with different values, found '{s}'")),
+    }
+}
+
+fn load_library(lib: Option<&str>, mojo: &str, accepted: &VersionRange) -> daimojo::Result<DaiMojoLibrary> {
+    let lib = DaiMojoLibrary::discover_accepting(lib.map(Path::new), Some(Path::new(mojo)), accepted)?;
+    log::info!("Library's daimojo version is {}", lib.version());
     Ok(lib)
 }
 
 fn load_model<'a>(lib: &'a DaiMojoLibrary, mojo: &str) -> daimojo::Result<RawModel<'a>> {
     log::debug!("Loading mojo: '{mojo}'");
-    let model = RawModel::load(&lib, mojo, ".")?;
+    let model = RawModel::load(lib, mojo, ".")?;
     Ok(model)
 }
 
+mod batch_sizing;
+mod cmd_bench;
+mod cmd_compare_runtimes;
+mod cmd_gen_input;
 mod cmd_predict;
+mod cmd_watch;
@@ -0,0 +1,319 @@
+//! Cache of loaded models, reloading them when their files change
+//!
+//! Models are shared as [Rc]; a reload replaces the cached model, while callers still holding
+//! the previous one can finish their transforms with it. The previous model is dropped with its last reference.
+//! The runtime does not document that a model can be used by several threads, so the manager and its models
+//! stay on the thread that created them; files with the same content share one model.
+
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant, SystemTime};
+use sha2::{Digest, Sha256};
+use crate::{error, DaiMojoLibrary, MOJO_DataType, MOJO_Transform_Ops, MojoError, RawModel, RawPipeline};
+
+/// Default interval for [ModelManager::poll_if_due].
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Input features and prediction outputs of a model.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Schema {
+    pub features: Vec<(String, MOJO_DataType)>,
+    pub outputs: Vec<(String, MOJO_DataType)>,
+}
+
+impl Schema {
+    fn of(model: &RawModel) -> error::Result<Self> {
+        let pipeline = RawPipeline::new(model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops)?;
+        Ok(Self {
+            features: model.features().map(|(name, data_type)| (name.to_string(), data_type)).collect(),
+            outputs: pipeline.outputs().map(|(name, data_type)| (name.to_string(), data_type)).collect(),
+        })
+    }
+
+    /// Describe the first difference that would break callers of `self`, if any.
+    pub fn incompatibility(&self, new: &Schema) -> Option<String> {
+        for (kind, old, new) in [("feature", &self.features, &new.features), ("output", &self.outputs, &new.outputs)] {
+            if old.len() != new.len() {
+                return Some(format!("{kind} count changed from {} to {}", old.len(), new.len()));
+            }
+            if let Some((old, new)) = old.iter().zip(new).find(|(old, new)| old != new) {
+                return Some(format!("{kind} '{}': {:?} changed to '{}': {:?}", old.0, old.1, new.0, new.1));
+            }
+        }
+        None
+    }
+}
+
+/// Loaded model, shared by all cached paths with the same content.
+pub struct CachedModel<'a> {
+    /// Path the model was loaded from
+    pub path: PathBuf,
+    /// SHA-256 of the file content, in hex
+    pub hash: String,
+    pub schema: Schema,
+    model: RawModel<'a>,
+}
+
+impl<'a> CachedModel<'a> {
+    pub fn model(&self) -> &RawModel<'a> {
+        &self.model
+    }
+}
+
+/// Cached model with the state of its file, to cheaply detect changes.
+struct Entry<'a> {
+    model: Rc<CachedModel<'a>>,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// Result of reloading a changed model file.
+#[derive(Debug)]
+pub enum Reload {
+    /// New model is in place
+    Swapped { path: PathBuf, old_hash: String, new_hash: String },
+    /// Previous model stays in place, as the new one could not be loaded or has a different schema
+    Rejected { path: PathBuf, error: MojoError },
+}
+
+pub struct ModelManager<'a> {
+    lib: &'a DaiMojoLibrary,
+    tf_lib_prefix: String,
+    poll_interval: Duration,
+    last_poll: Cell<Option<Instant>>,
+    entries: RefCell<HashMap<PathBuf, Entry<'a>>>,
+    /// Models by content hash, so that identical files at different paths share one model
+    by_hash: RefCell<HashMap<String, Weak<CachedModel<'a>>>>,
+}
+
+impl<'a> ModelManager<'a> {
+    pub fn new(lib: &'a DaiMojoLibrary, tf_lib_prefix: &str) -> Self {
+        Self {
+            lib,
+            tf_lib_prefix: tf_lib_prefix.to_string(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            last_poll: Cell::new(None),
+            entries: RefCell::new(HashMap::new()),
+            by_hash: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Get the model from cache, loading it on first use.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> error::Result<Rc<CachedModel<'a>>> {
+        let path = canonical_model_path(path.as_ref())?;
+        if let Some(entry) = self.entries().get(&path) {
+            return Ok(entry.model.clone());
+        }
+        let metadata = std::fs::metadata(&path)?;
+        let model = self.load(&path, content_hash(&path)?)?;
+        let entry = Entry { model: model.clone(), modified: metadata.modified().ok(), len: metadata.len() };
+        self.entries_mut().insert(path, entry);
+        Ok(model)
+    }
+
+    /// Paths of all cached models.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.entries().keys().cloned().collect()
+    }
+
+    /// Forget the model; it is dropped once no caller holds it.
+    pub fn evict<P: AsRef<Path>>(&self, path: P) -> Option<Rc<CachedModel<'a>>> {
+        let path = path.as_ref().canonicalize().ok()?;
+        self.entries_mut().remove(&path).map(|entry| entry.model)
+    }
+
+    /// Like [ModelManager::poll], but only when the poll interval has elapsed since the last poll.
+    /// Cheap enough to be called before every request.
+    pub fn poll_if_due(&self) -> Vec<Reload> {
+        if matches!(self.last_poll.get(), Some(last_poll) if last_poll.elapsed() < self.poll_interval) {
+            return Vec::new();
+        }
+        self.poll()
+    }
+
+    /// Check all cached model files and reload those whose content has changed.
+    /// A removed file is not a change; the model stays cached.
+    pub fn poll(&self) -> Vec<Reload> {
+        self.last_poll.set(Some(Instant::now()));
+        let mut reloads = Vec::new();
+        for path in self.paths() {
+            let Ok(metadata) = std::fs::metadata(&path) else {
+                log::warn!("Cannot check model file '{}'", path.display());
+                continue;
+            };
+            let old = {
+                let mut entries = self.entries_mut();
+                let Some(entry) = entries.get_mut(&path) else { continue };
+                if metadata.len() == entry.len && metadata.modified().ok() == entry.modified {
+                    continue;
+                }
+                entry.len = metadata.len();
+                entry.modified = metadata.modified().ok();
+                entry.model.clone()
+            };
+            let reload = match content_hash(&path) {
+                Ok(hash) if hash == old.hash => continue,
+                Ok(hash) => {
+                    log::info!("Model file '{}' changed, reloading", path.display());
+                    self.reload(&path, &old, hash)
+                }
+                Err(error) => Reload::Rejected { path, error },
+            };
+            if let Reload::Rejected { path, error } = &reload {
+                log::error!("Keeping previous model '{}': {error}", path.display());
+            }
+            reloads.push(reload);
+        }
+        reloads
+    }
+
+    fn reload(&self, path: &Path, old: &CachedModel<'a>, hash: String) -> Reload {
+        let new = match self.load(path, hash.clone()) {
+            Ok(new) => new,
+            Err(error) => return Reload::Rejected { path: path.to_path_buf(), error },
+        };
+        if let Some(reason) = old.schema.incompatibility(&new.schema) {
+            let error = MojoError::SchemaMismatch { path: path.to_path_buf(), reason };
+            return Reload::Rejected { path: path.to_path_buf(), error };
+        }
+        if let Some(entry) = self.entries_mut().get_mut(path) {
+            entry.model = new;
+        }
+        Reload::Swapped { path: path.to_path_buf(), old_hash: old.hash.clone(), new_hash: hash }
+    }
+
+    fn load(&self, path: &Path, hash: String) -> error::Result<Rc<CachedModel<'a>>> {
+        let shared = self.by_hash.borrow().get(&hash).and_then(Weak::upgrade);
+        if let Some(cached) = shared {
+            log::debug!("Model '{}' has same content as '{}'", path.display(), cached.path.display());
+            return Ok(cached);
+        }
+        // the maps are not borrowed while loading, which takes a while
+        let model = RawModel::load(self.lib, path, &self.tf_lib_prefix)?;
+        let cached = Rc::new(CachedModel {
+            path: path.to_path_buf(),
+            hash: hash.clone(),
+            schema: Schema::of(&model)?,
+            model,
+        });
+        let mut by_hash = self.by_hash.borrow_mut();
+        by_hash.retain(|_, model| model.strong_count() > 0);
+        by_hash.insert(hash, Rc::downgrade(&cached));
+        Ok(cached)
+    }
+
+    fn entries(&self) -> Ref<'_, HashMap<PathBuf, Entry<'a>>> {
+        self.entries.borrow()
+    }
+
+    fn entries_mut(&self) -> RefMut<'_, HashMap<PathBuf, Entry<'a>>> {
+        self.entries.borrow_mut()
+    }
+}
+
+fn canonical_model_path(path: &Path) -> error::Result<PathBuf> {
+    path.canonicalize().map_err(|source| match source.kind() {
+        ErrorKind::NotFound => MojoError::ModelNotFound(path.to_path_buf()),
+        _ => MojoError::InvalidPath { path: path.to_path_buf(), source },
+    })
+}
+
+/// SHA-256 of the file, stable across processes, so that it can be compared with hashes reported elsewhere.
+fn content_hash(path: &Path) -> error::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let count = file.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.update(&buffer[..count]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::Path;
+    use std::rc::Rc;
+    use crate::{DaiMojoLibrary, MOJO_DataType, MOJO_Transform_Ops, RawFrame, RawPipeline};
+    use crate::test_support::{licensed_fake_runtime, TempDir};
+    use super::{ModelManager, Reload, Schema};
+
+    fn append(path: &Path, data: &[u8]) {
+        std::fs::OpenOptions::new().append(true).open(path).unwrap().write_all(data).unwrap();
+    }
+
+    #[test]
+    fn schema_incompatibility() {
+        let old = Schema {
+            features: vec![("a".to_string(), MOJO_DataType::MOJO_FLOAT)],
+            outputs: vec![("y".to_string(), MOJO_DataType::MOJO_DOUBLE)],
+        };
+        assert_eq!(None, old.incompatibility(&old.clone()));
+        let mut new = old.clone();
+        new.features[0].1 = MOJO_DataType::MOJO_DOUBLE;
+        assert_eq!(Some("feature 'a': MOJO_FLOAT changed to 'a': MOJO_DOUBLE".to_string()), old.incompatibility(&new));
+        let mut new = old.clone();
+        new.outputs.clear();
+        assert_eq!(Some("output count changed from 1 to 0".to_string()), old.incompatibility(&new));
+    }
+
+    #[test]
+    fn reload_changed_file() {
+        let dir = TempDir::new("reload_changed_file");
+        let dir = dir.path();
+        let path = dir.join("pipeline.mojo");
+        let copy = dir.join("copy.mojo");
+        std::fs::copy("data/iris/pipeline.mojo", &path).unwrap();
+        std::fs::copy("data/iris/pipeline.mojo", &copy).unwrap();
+        let lib = DaiMojoLibrary::load(licensed_fake_runtime()).unwrap();
+        let manager = ModelManager::new(&lib, "");
+
+        let first = manager.get(&path).unwrap();
+        assert!(Rc::ptr_eq(&first, &manager.get(&path).unwrap()));
+        assert!(Rc::ptr_eq(&first, &manager.get(&copy).unwrap()), "same content shares the model");
+        assert!(manager.poll().is_empty());
+
+        append(&path, b"changed");
+        let reloads = manager.poll();
+        assert!(matches!(reloads.as_slice(), [Reload::Swapped { old_hash, new_hash, .. }] if old_hash != new_hash));
+        let second = manager.get(&path).unwrap();
+        assert!(!Rc::ptr_eq(&first, &second));
+        // in-flight users keep the previous model
+        assert!(first.model().is_valid());
+        assert!(Rc::ptr_eq(&first, &manager.get(&copy).unwrap()), "unchanged copy keeps the model");
+        assert!(manager.poll_if_due().is_empty());
+    }
+
+    #[test]
+    fn swap_while_scoring() {
+        let dir = TempDir::new("swap_while_scoring");
+        let path = dir.path().join("pipeline.mojo");
+        std::fs::copy("data/iris/pipeline.mojo", &path).unwrap();
+        let lib = DaiMojoLibrary::load(licensed_fake_runtime()).unwrap();
+        let manager = ModelManager::new(&lib, "");
+
+        let old = manager.get(&path).unwrap();
+        let pipeline = RawPipeline::new(old.model(), MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let frame = RawFrame::new(&pipeline, 1).unwrap();
+        pipeline.transform(&frame, 1, false).unwrap();
+        append(&path, b"changed");
+        assert!(matches!(manager.poll().as_slice(), [Reload::Swapped { .. }]));
+        // the previous model still scores, and the manager hands out the new one
+        pipeline.transform(&frame, 1, false).unwrap();
+        let new = manager.get(&path).unwrap();
+        assert!(!Rc::ptr_eq(&old, &new));
+        assert_ne!(old.hash, new.hash);
+    }
+}
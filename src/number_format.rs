@@ -0,0 +1,369 @@
+//! Text representation of numeric values on CSV export
+
+use std::fmt::Write;
+use std::str::FromStr;
+use crate::daimojo_library::ColumnValue;
+
+/// How floating point values are written.
+///
+/// Except for [Notation::Shortest], values are written like `String.format` of the Java MOJO scorer does:
+/// the shortest decimal representation is rounded half up, and infinities are written as `Infinity`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Notation {
+    /// Shortest text that reads back as the same value
+    #[default]
+    Shortest,
+    /// Given number of digits after the decimal point, like `%.Nf`
+    Fixed(usize),
+    /// Given number of significant digits, like `%.NG`: in scientific notation when the exponent
+    /// is below -4 or not below N, with trailing zeros
+    Significant(usize),
+    /// Scientific notation with given number of digits after the decimal point, like `%.NE`: `1.5E-01`
+    Scientific(usize),
+}
+
+/// Parses `shortest`, `fixed:N`, `sig:N` or `sci:N`.
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, digits) = s.split_once(':').unwrap_or((s, ""));
+        let digits = || digits.parse::<usize>().map_err(|_| format!("invalid number of digits in '{s}'"));
+        match kind {
+            "shortest" => Ok(Notation::Shortest),
+            "fixed" => Ok(Notation::Fixed(digits()?)),
+            "sig" => match digits()? {
+                0 => Err(format!("at least one significant digit is needed in '{s}'")),
+                digits => Ok(Notation::Significant(digits)),
+            },
+            "sci" => Ok(Notation::Scientific(digits()?)),
+            _ => Err(format!("unknown notation '{s}', expected one of: shortest, fixed:N, sig:N, sci:N")),
+        }
+    }
+}
+
+impl Notation {
+    fn write(&self, out: &mut String, value: f64) {
+        match *self {
+            Notation::Shortest => {
+                let _ = write!(out, "{value}");
+            }
+            _ if value.is_nan() => out.push_str("NaN"),
+            _ if value.is_infinite() => out.push_str(if value < 0.0 { "-Infinity" } else { "Infinity" }),
+            Notation::Fixed(decimals) => {
+                let decimal = Decimal::of(value);
+                let significant = decimal.exponent + 1 + decimals as i32;
+                decimal.round(significant).write_fixed(out, decimals)
+            }
+            Notation::Scientific(decimals) => Decimal::of(value).round(decimals as i32 + 1).write_scientific(out, decimals),
+            Notation::Significant(digits) => {
+                let rounded = Decimal::of(value).round(digits as i32);
+                if rounded.is_zero() || (-4..digits as i32).contains(&rounded.exponent) {
+                    let decimals = (digits as i32 - 1 - rounded.exponent).max(0) as usize;
+                    rounded.write_fixed(out, decimals)
+                } else {
+                    rounded.write_scientific(out, digits - 1)
+                }
+            }
+        }
+    }
+}
+
+/// Shortest representation of an `f64` has at most 17 significant digits.
+const MAX_DIGITS: usize = 17;
+
+/// Decimal digits `d.ddd` times 10 to the exponent; no digits for zero.
+struct Decimal {
+    negative: bool,
+    digits: [u8; MAX_DIGITS],
+    len: usize,
+    exponent: i32,
+}
+
+impl Decimal {
+    /// Shortest digits that read back as the value, which must be finite.
+    fn of(value: f64) -> Self {
+        let mut decimal = Self { negative: value.is_sign_negative(), digits: [0; MAX_DIGITS], len: 0, exponent: 0 };
+        let mut buffer = ryu::Buffer::new();
+        // like `0.15`, `123.0` or `1.5e-7`
+        let text = buffer.format_finite(value.abs());
+        let (mantissa, exponent) = text.split_once('e').unwrap_or((text, "0"));
+        let point = mantissa.find('.').unwrap_or(mantissa.len()) as i32;
+        let mut position = 0;
+        for digit in mantissa.bytes().filter(u8::is_ascii_digit).map(|b| b - b'0') {
+            if decimal.len == 0 && digit == 0 {
+                position += 1;
+                continue;
+            }
+            if decimal.len == 0 {
+                decimal.exponent = point - 1 - position + exponent.parse::<i32>().expect("exponent is a number");
+            }
+            decimal.digits[decimal.len] = digit;
+            decimal.len += 1;
+        }
+        decimal.trim();
+        decimal
+    }
+
+    fn is_zero(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Drop trailing zeros.
+    fn trim(&mut self) {
+        while self.len > 0 && self.digits[self.len - 1] == 0 {
+            self.len -= 1;
+        }
+        if self.len == 0 {
+            self.exponent = 0;
+        }
+    }
+
+    /// Round half up to given number of significant digits, which can be zero or negative when rounding
+    /// to a position before the first digit.
+    fn round(mut self, significant: i32) -> Self {
+        if significant < 0 {
+            self.len = 0;
+        }
+        let keep = significant.max(0) as usize;
+        if self.len <= keep {
+            self.trim();
+            return self;
+        }
+        let round_up = self.digits[keep] >= 5;
+        self.len = keep;
+        if round_up {
+            match self.digits[..keep].iter().rposition(|&d| d < 9) {
+                Some(last) => {
+                    self.digits[last] += 1;
+                    self.len = last + 1;
+                }
+                None => {
+                    // all nines, or nothing kept: carry into a new leading digit
+                    self.digits[0] = 1;
+                    self.len = 1;
+                    self.exponent += 1;
+                }
+            }
+        }
+        self.trim();
+        self
+    }
+
+    /// Digit at the power of ten.
+    fn digit(&self, power: i32) -> char {
+        let index = self.exponent - power;
+        let digit = if index < 0 || index as usize >= self.len { 0 } else { self.digits[index as usize] };
+        (b'0' + digit) as char
+    }
+
+    fn write_fixed(&self, out: &mut String, decimals: usize) {
+        if self.negative {
+            out.push('-');
+        }
+        for power in (0..=self.exponent.max(0)).rev() {
+            out.push(self.digit(power));
+        }
+        if decimals > 0 {
+            out.push('.');
+            for power in 1..=decimals as i32 {
+                out.push(self.digit(-power));
+            }
+        }
+    }
+
+    fn write_scientific(&self, out: &mut String, decimals: usize) {
+        if self.negative {
+            out.push('-');
+        }
+        out.push(self.digit(self.exponent));
+        if decimals > 0 {
+            out.push('.');
+            for index in 1..=decimals as i32 {
+                out.push(self.digit(self.exponent - index));
+            }
+        }
+        let sign = if self.exponent < 0 { '-' } else { '+' };
+        let _ = write!(out, "E{sign}{:02}", self.exponent.abs());
+    }
+}
+
+/// Formatting of numeric columns, applied per [crate::MOJO_DataType].
+#[derive(Clone, Debug)]
+pub struct NumberFormat {
+    /// Notation of `MOJO_FLOAT` values
+    pub float: Notation,
+    /// Notation of `MOJO_DOUBLE` values
+    pub double: Notation,
+    /// Replaces `.` in floating point values
+    pub decimal_separator: char,
+    /// Written for missing values of all numeric types, like `NA` or an empty token;
+    /// by default, the stored value is written, like `NaN` or `2147483647`
+    pub na: Option<String>,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self { float: Notation::Shortest, double: Notation::Shortest, decimal_separator: '.', na: None }
+    }
+}
+
+impl NumberFormat {
+    /// Use the same notation for floats and doubles.
+    pub fn with_notation(mut self, notation: Notation) -> Self {
+        self.float = notation;
+        self.double = notation;
+        self
+    }
+
+    pub fn format_f32(&self, value: Option<f32>) -> String {
+        let mut out = String::new();
+        self.write_f32(&mut out, value);
+        out
+    }
+
+    pub fn format_f64(&self, value: Option<f64>) -> String {
+        let mut out = String::new();
+        self.write_f64(&mut out, value);
+        out
+    }
+
+    pub fn format_int<T: itoa::Integer + ColumnValue>(&self, value: Option<T>) -> String {
+        let mut out = String::new();
+        self.write_int(&mut out, value);
+        out
+    }
+
+    /// Append the value to `out`, which is reused across values to avoid allocating for each of them.
+    pub fn write_f32(&self, out: &mut String, value: Option<f32>) {
+        match value {
+            None => self.write_na(out, "NaN"),
+            // shortest text of the f32 itself, not of its f64 conversion
+            Some(value) if self.float == Notation::Shortest => {
+                let start = out.len();
+                let _ = write!(out, "{value}");
+                self.replace_separator(out, start);
+            }
+            Some(value) => self.write_float(out, value as f64, self.float),
+        }
+    }
+
+    pub fn write_f64(&self, out: &mut String, value: Option<f64>) {
+        match value {
+            None => self.write_na(out, "NaN"),
+            Some(value) => self.write_float(out, value, self.double),
+        }
+    }
+
+    pub fn write_int<T: itoa::Integer + ColumnValue>(&self, out: &mut String, value: Option<T>) {
+        match value {
+            Some(value) => out.push_str(itoa::Buffer::new().format(value)),
+            None => self.write_na(out, itoa::Buffer::new().format(T::NA)),
+        }
+    }
+
+    /// Write the NA token, or `stored` when there is none.
+    fn write_na(&self, out: &mut String, stored: &str) {
+        out.push_str(self.na.as_deref().unwrap_or(stored))
+    }
+
+    fn write_float(&self, out: &mut String, value: f64, notation: Notation) {
+        let start = out.len();
+        notation.write(out, value);
+        self.replace_separator(out, start);
+    }
+
+    /// Replace the decimal point of the number written from `start`.
+    fn replace_separator(&self, out: &mut String, start: usize) {
+        if self.decimal_separator == '.' {
+            return;
+        }
+        if let Some(point) = out[start..].find('.') {
+            let point = start + point;
+            out.replace_range(point..point + 1, self.decimal_separator.encode_utf8(&mut [0; 4]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Notation, NumberFormat};
+
+    fn format(notation: Notation, value: f64) -> String {
+        let mut out = String::new();
+        notation.write(&mut out, value);
+        out
+    }
+
+    #[test]
+    fn notations() {
+        assert_eq!("0.28463825583457947", format(Notation::Shortest, 0.28463825583457947));
+        assert_eq!("0.2846", format(Notation::Fixed(4), 0.28463825583457947));
+        assert_eq!("0.284638", format(Notation::Significant(6), 0.28463825583457947));
+        assert_eq!("1.50000", format(Notation::Significant(6), 1.5));
+        assert_eq!("10.0", format(Notation::Significant(3), 10.0));
+        assert_eq!("1.23E+05", format(Notation::Significant(3), 123456.0));
+        assert_eq!("-0.00123", format(Notation::Significant(3), -0.0012345));
+        assert_eq!("1.23E-05", format(Notation::Significant(3), 0.0000123456));
+        assert_eq!("2.846E-01", format(Notation::Scientific(3), 0.28463825583457947));
+        assert_eq!("1.5E-01", format(Notation::Scientific(1), 0.15));
+        assert_eq!(Ok(Notation::Significant(6)), "sig:6".parse());
+        assert_eq!(Ok(Notation::Shortest), "shortest".parse());
+        assert!("sig:0".parse::<Notation>().is_err());
+        assert!("round:2".parse::<Notation>().is_err());
+    }
+
+    /// Expected values are outputs of `String.format` in Java.
+    #[test]
+    fn like_java() {
+        assert_eq!("0.13", format(Notation::Fixed(2), 0.125));
+        assert_eq!("0.2", format(Notation::Fixed(1), 0.15));
+        assert_eq!("1", format(Notation::Fixed(0), 0.5));
+        assert_eq!("0", format(Notation::Fixed(0), 0.4));
+        assert_eq!("0.00", format(Notation::Fixed(2), 0.004));
+        assert_eq!("-0.0", format(Notation::Fixed(1), -0.01));
+        assert_eq!("10.00", format(Notation::Fixed(2), 9.999));
+        assert_eq!("1234567.0", format(Notation::Fixed(1), 1234567.0));
+        assert_eq!("1.00E+01", format(Notation::Scientific(2), 9.999));
+        assert_eq!("0.00E+00", format(Notation::Scientific(2), 0.0));
+        assert_eq!("2E-01", format(Notation::Scientific(0), 0.15));
+        assert_eq!("1.5E+100", format(Notation::Scientific(1), 1.5e100));
+        assert_eq!("0.00", format(Notation::Significant(3), 0.0));
+        assert_eq!("0.000100", format(Notation::Significant(3), 0.0001));
+        assert_eq!("1.00E+03", format(Notation::Significant(3), 999.9));
+        assert_eq!("100", format(Notation::Significant(3), 99.95));
+        assert_eq!("NaN", format(Notation::Fixed(2), f64::NAN));
+        assert_eq!("-Infinity", format(Notation::Scientific(2), f64::NEG_INFINITY));
+        assert_eq!("Infinity", format(Notation::Significant(2), f64::INFINITY));
+        assert_eq!("1.0E+20", format(Notation::Scientific(1), 1e20));
+        assert_eq!("1.50E-07", format(Notation::Scientific(2), 1.5e-7));
+        assert_eq!("123.00", format(Notation::Fixed(2), 123.0));
+    }
+
+    #[test]
+    fn number_format() {
+        let default = NumberFormat::default();
+        assert_eq!("0.28463826", default.format_f32(Some(0.28463826)));
+        assert_eq!("NaN", default.format_f32(None));
+        assert_eq!("NaN", default.format_f64(None));
+        assert_eq!("2147483647", default.format_int::<i32>(None));
+        assert_eq!(crate::MOJO_INT64_NAN.to_string(), default.format_int::<i64>(None));
+        let empty = NumberFormat { na: Some(String::new()), ..Default::default() };
+        assert_eq!("", empty.format_f64(None));
+        assert_eq!("", empty.format_int::<i32>(None));
+
+        let format = NumberFormat { decimal_separator: ',', na: Some("NA".to_string()), ..Default::default() }
+            .with_notation(Notation::Fixed(2));
+        assert_eq!("0,28", format.format_f32(Some(0.28463826)));
+        assert_eq!("NA", format.format_f64(None));
+        assert_eq!("NA", format.format_int::<i64>(None));
+        assert_eq!("-7", format.format_int(Some(-7)));
+
+        // values appended to one buffer
+        let mut out = String::new();
+        format.write_f64(&mut out, Some(1.5));
+        format.write_int(&mut out, Some(12));
+        format.write_f32(&mut out, None);
+        assert_eq!("1,5012NA", out);
+    }
+}
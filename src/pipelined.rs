@@ -0,0 +1,202 @@
+//! Pipelined scoring: parsing, scoring and writing overlap on three threads
+//!
+//! Importing into the frame, the transform and formatting of the output stay on the calling thread, with one frame.
+//! Meanwhile, a reader thread parses the next batch into byte records, and a writer thread writes out the previous output.
+//! Both hand over their buffers through bounded channels and get them back for reuse,
+//! so memory stays bounded and rows keep their order.
+//! When the batch size adapts, the reader picks up the new size with its next batch,
+//! so batches already in flight keep their size.
+
+use std::io::{ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::time::Instant;
+use crate::batch_sizer::BatchSizer;
+use crate::csv_export::FlushPolicy;
+use crate::daimojo_library::{RawFrame, RawPipeline};
+use crate::{error, CsvFormat, FrameExporter, FrameImporter};
+
+/// Number of batches being parsed or written while the current one is scored.
+pub const IN_FLIGHT: usize = 2;
+
+/// Batch of parsed records; only the first `rows` are valid, the rest keep their allocations for reuse.
+type Batch = (Vec<csv::ByteRecord>, usize);
+
+/// Score CSV input batch by batch, writing output as CSV. Returns the number of scored rows.
+pub fn score_pipelined<R: Read + Send, W: Write + Send, B: BatchSizer>(
+    pipeline: &RawPipeline,
+    mut rdr: csv::Reader<R>,
+    writer: W,
+    mut batch_size: B,
+    format: &CsvFormat,
+    flush_policy: FlushPolicy,
+) -> error::Result<usize> {
+    let frame = RawFrame::new(pipeline, batch_size.capacity())?;
+    let mut importer = FrameImporter::init(pipeline, &frame, &mut rdr)?
+        .with_bool_format(format.bools.clone());
+
+    let next_batch_size = AtomicUsize::new(batch_size.current());
+    std::thread::scope(|scope| {
+        let (batch_tx, batch_rx) = mpsc::sync_channel(IN_FLIGHT);
+        let (free_tx, free_rx) = mpsc::channel();
+        for _ in 0..IN_FLIGHT {
+            free_tx.send(Vec::new()).expect("receiver is alive");
+        }
+        let (chunk_tx, chunk_rx) = mpsc::sync_channel(IN_FLIGHT);
+        let (spare_tx, spare_rx) = mpsc::channel();
+        let next_batch_size = &next_batch_size;
+        scope.spawn(move || read_batches(rdr, next_batch_size, batch_tx, free_rx));
+        let write_thread = scope.spawn(move || write_chunks(writer, chunk_rx, spare_tx));
+
+        let chunks = ChunkSender { chunk: Vec::new(), full: chunk_tx, spare: spare_rx };
+        let mut exporter = FrameExporter::with_writer(pipeline, &frame, chunks)?
+            .with_bool_format(format.bools.clone())
+            .with_number_format(format.numbers.clone())
+            .with_flush_policy(flush_policy);
+        let scored = (|| {
+            for batch in batch_rx {
+                let (records, rows) = batch?;
+                importer.import_byte_records(&records[..rows])?;
+                let start = Instant::now();
+                pipeline.transform(&frame, rows, false)?;
+                log::debug!("-- batch {rows} rows");
+                next_batch_size.store(batch_size.update(rows, start.elapsed()), Ordering::Relaxed);
+                exporter.export_frame(rows)?;
+                // the reader is gone when it reached the end
+                let _ = free_tx.send(records);
+            }
+            exporter.flush()
+        })();
+        let rows = exporter.saved_rows;
+        // closes the channel, so that the writer finishes
+        drop(exporter);
+        let written = write_thread.join().expect("writer thread panicked");
+        // a failed write shows up as a closed channel on this side; report the original error
+        written?;
+        scored?;
+        Ok(rows)
+    })
+}
+
+/// Parse batches into buffers coming back from the scoring thread, until the input ends or the scoring thread is gone.
+fn read_batches<R: Read>(mut rdr: csv::Reader<R>, next_batch_size: &AtomicUsize, batches: SyncSender<csv::Result<Batch>>, free: Receiver<Vec<csv::ByteRecord>>) {
+    for mut records in free {
+        let batch_size = next_batch_size.load(Ordering::Relaxed);
+        if records.len() < batch_size {
+            records.resize_with(batch_size, csv::ByteRecord::new);
+        }
+        let mut rows = 0;
+        while rows < batch_size {
+            match rdr.read_byte_record(&mut records[rows]) {
+                Ok(true) => rows += 1,
+                Ok(false) => break,
+                Err(e) => {
+                    let _ = batches.send(Err(e));
+                    return;
+                }
+            }
+        }
+        if rows == 0 || batches.send(Ok((records, rows))).is_err() || rows < batch_size {
+            return;
+        }
+    }
+}
+
+/// Write chunks of output until the sending side closes, returning their buffers for reuse.
+fn write_chunks<W: Write>(mut writer: W, chunks: Receiver<Vec<u8>>, spare: Sender<Vec<u8>>) -> std::io::Result<()> {
+    for mut chunk in chunks {
+        writer.write_all(&chunk)?;
+        writer.flush()?;
+        chunk.clear();
+        let _ = spare.send(chunk);
+    }
+    writer.flush()
+}
+
+/// Collects output and hands it over to the writer thread on each flush.
+struct ChunkSender {
+    chunk: Vec<u8>,
+    full: SyncSender<Vec<u8>>,
+    spare: Receiver<Vec<u8>>,
+}
+
+impl Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let next = self.spare.try_recv().unwrap_or_default();
+        let chunk = std::mem::replace(&mut self.chunk, next);
+        self.full.send(chunk)
+            .map_err(|_| std::io::Error::new(ErrorKind::BrokenPipe, "writer thread stopped"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::{BatchSizer, CsvFormat, DaiMojoLibrary, FlushPolicy, FrameExporter, FrameImporter, MOJO_Transform_Ops, RawFrame, RawModel, RawPipeline};
+    use crate::test_support::ECHO_MODEL;
+    use super::score_pipelined;
+
+    #[test]
+    fn same_output_as_sequential() {
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, ECHO_MODEL, "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        // every third row has missing values
+        let line = |i: i32| match i % 3 {
+            0 => format!("{},{i},{},{}.5,{}.25,s{i}\n", i % 2 == 0, -i, i, i),
+            _ => format!("{},{i},,,,\n", i % 2 == 0),
+        };
+        let data: String = std::iter::once("bool,int32,int64,float,double,string\n".to_string()).chain((0..25).map(line)).collect();
+
+        let mut sequential = Vec::new();
+        {
+            let frame = RawFrame::new(&pipeline, 10).unwrap();
+            let mut rdr = csv::Reader::from_reader(data.as_bytes());
+            let mut importer = FrameImporter::init(&pipeline, &frame, &mut rdr).unwrap();
+            let mut exporter = FrameExporter::with_writer(&pipeline, &frame, &mut sequential).unwrap();
+            while let Some(rows) = importer.import_batch(&mut rdr).unwrap() {
+                pipeline.transform(&frame, rows, false).unwrap();
+                exporter.export_frame(rows).unwrap();
+            }
+            exporter.flush().unwrap();
+        }
+        let sequential_text = String::from_utf8(sequential.clone()).unwrap();
+        let lines: Vec<&str> = sequential_text.lines().collect();
+        assert_eq!(26, lines.len());
+        assert_eq!("true,0,0,0.5,0.25,s0", lines[1]);
+        assert_eq!("false,1,9223372036854775807,NaN,NaN,", lines[2]);
+        assert_eq!("true,24,-24,24.5,24.25,s24", lines[25]);
+
+        for policy in [FlushPolicy::Batch, FlushPolicy::Line] {
+            let mut pipelined = Vec::new();
+            let rdr = csv::Reader::from_reader(data.as_bytes());
+            let rows = score_pipelined(&pipeline, rdr, &mut pipelined, 10, &CsvFormat::default(), policy).unwrap();
+            assert_eq!(25, rows);
+            assert_eq!(sequential, pipelined);
+        }
+
+        // batches of 1, 3, 5, ... rows, up to the frame capacity
+        struct Growing(usize);
+        impl BatchSizer for Growing {
+            fn capacity(&self) -> usize { 10 }
+            fn current(&self) -> usize { self.0 }
+            fn update(&mut self, _rows: usize, _elapsed: Duration) -> usize {
+                self.0 = (self.0 + 2).min(10);
+                self.0
+            }
+        }
+        let mut pipelined = Vec::new();
+        let rdr = csv::Reader::from_reader(data.as_bytes());
+        let rows = score_pipelined(&pipeline, rdr, &mut pipelined, Growing(1), &CsvFormat::default(), FlushPolicy::Batch).unwrap();
+        assert_eq!(25, rows);
+        assert_eq!(sequential, pipelined);
+    }
+}
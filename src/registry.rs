@@ -0,0 +1,301 @@
+//! Registry of models, routing requests by name, version or UUID
+//!
+//! Models are registered either from a directory tree laid out as `ROOT/NAME/VERSION/*.mojo`,
+//! or from a JSON manifest like:
+//! ```json
+//! {"models": [{"name": "iris", "version": "2", "path": "iris-v2.mojo", "default": true}]}
+//! ```
+//! Relative paths in the manifest are resolved against the manifest's directory.
+//!
+//! Requests are routed by a spec, which is one of:
+//! * `NAME` or `NAME@default` - the version marked as default, or the latest one if none is marked
+//! * `NAME@latest` - the highest version
+//! * `NAME@VERSION` - exact version
+//! * `UUID` - the model with this UUID; the highest version if there are more
+//!
+//! A spec without `@` is a name first: it is looked up as a UUID only when no model has that name.
+//! The aliases `default` and `latest` cannot be registered as versions, names cannot contain `@`,
+//! and a manifest marks at most one version of a name as default.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use serde::Deserialize;
+use crate::{error, DaiMojoLibrary, MojoError, ModelManager};
+use crate::model_manager::CachedModel;
+
+/// Version alias of the default model.
+pub const DEFAULT_ALIAS: &str = "default";
+/// Version alias of the highest version.
+pub const LATEST_ALIAS: &str = "latest";
+
+#[derive(Deserialize)]
+struct Manifest {
+    models: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct ManifestEntry {
+    name: String,
+    version: String,
+    path: PathBuf,
+    #[serde(default)]
+    default: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct RegisteredModel {
+    pub name: String,
+    pub version: String,
+    pub uuid: String,
+    pub path: PathBuf,
+}
+
+pub struct Registry<'a> {
+    manager: ModelManager<'a>,
+    models: Vec<RegisteredModel>,
+    /// Indexes into `models` by name, sorted by version
+    by_name: HashMap<String, Vec<usize>>,
+    by_uuid: HashMap<String, Vec<usize>>,
+    defaults: HashMap<String, usize>,
+}
+
+impl<'a> Registry<'a> {
+    pub fn new(lib: &'a DaiMojoLibrary, tf_lib_prefix: &str) -> Self {
+        Self::with_manager(ModelManager::new(lib, tf_lib_prefix))
+    }
+
+    /// Use given manager, for example with custom poll interval.
+    pub fn with_manager(manager: ModelManager<'a>) -> Self {
+        Self {
+            manager,
+            models: Vec::new(),
+            by_name: HashMap::new(),
+            by_uuid: HashMap::new(),
+            defaults: HashMap::new(),
+        }
+    }
+
+    /// Register all models found as `ROOT/NAME/VERSION/*.mojo`.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, root: P) -> error::Result<()> {
+        for name_dir in sorted_entries(root.as_ref())? {
+            if !name_dir.is_dir() {
+                continue;
+            }
+            for version_dir in sorted_entries(&name_dir)? {
+                if !version_dir.is_dir() {
+                    continue;
+                }
+                for mojo in sorted_entries(&version_dir)? {
+                    if mojo.extension().is_some_and(|ext| ext == "mojo") {
+                        self.register(&file_name(&name_dir), &file_name(&version_dir), &mojo)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Register all models listed in the manifest.
+    pub fn load_manifest<P: AsRef<Path>>(&mut self, manifest: P) -> error::Result<()> {
+        let file = std::fs::File::open(manifest.as_ref())
+            .map_err(|source| MojoError::InvalidPath { path: manifest.as_ref().to_path_buf(), source })?;
+        let manifest_dir = manifest.as_ref().parent().unwrap_or(Path::new("."));
+        let manifest: Manifest = serde_json::from_reader(std::io::BufReader::new(file))?;
+        for entry in manifest.models {
+            if entry.default && self.defaults.contains_key(&entry.name) {
+                return Err(MojoError::DuplicateDefault(entry.name));
+            }
+            let index = self.register(&entry.name, &entry.version, &manifest_dir.join(&entry.path))?;
+            if entry.default {
+                self.defaults.insert(entry.name, index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the model and register it; returns its index in [Registry::models].
+    pub fn register(&mut self, name: &str, version: &str, path: &Path) -> error::Result<usize> {
+        if name.contains('@') {
+            return Err(MojoError::InvalidModelName(name.to_string()));
+        }
+        if version == DEFAULT_ALIAS || version == LATEST_ALIAS {
+            return Err(MojoError::ReservedVersion(version.to_string()));
+        }
+        if self.find_version(name, version).is_some() {
+            return Err(MojoError::DuplicateModel(format!("{name}@{version}")));
+        }
+        let model = self.manager.get(path)?;
+        let uuid = model.model().uuid().to_string_lossy().to_string();
+        log::debug!("Registering {name}@{version} ({uuid}): {}", path.display());
+        let index = self.models.len();
+        self.models.push(RegisteredModel {
+            name: name.to_string(),
+            version: version.to_string(),
+            uuid: uuid.clone(),
+            path: path.canonicalize()?,
+        });
+        let versions = self.by_name.entry(name.to_string()).or_default();
+        versions.push(index);
+        versions.sort_by(|&a, &b| compare_versions(&self.models[a].version, &self.models[b].version));
+        self.by_uuid.entry(uuid).or_default().push(index);
+        Ok(index)
+    }
+
+    pub fn models(&self) -> &[RegisteredModel] {
+        &self.models
+    }
+
+    /// Find the registration for given spec, see module documentation.
+    pub fn resolve(&self, spec: &str) -> error::Result<&RegisteredModel> {
+        let index = match spec.split_once('@') {
+            Some((name, version)) => self.find_alias(name, version),
+            // names take precedence over UUIDs
+            None if self.by_name.contains_key(spec) => self.find_alias(spec, DEFAULT_ALIAS),
+            None => self.find_uuid(spec),
+        };
+        index.map(|index| &self.models[index])
+            .ok_or_else(|| MojoError::ModelNotRegistered(spec.to_string()))
+    }
+
+    /// Get the model for given spec, reloaded if its file has changed since the last poll.
+    pub fn route(&self, spec: &str) -> error::Result<Rc<CachedModel<'a>>> {
+        let registered = self.resolve(spec)?;
+        self.manager.poll_if_due();
+        self.manager.get(&registered.path)
+    }
+
+    /// Version or alias of a named model.
+    fn find_alias(&self, name: &str, version: &str) -> Option<usize> {
+        let versions = self.by_name.get(name)?;
+        match version {
+            LATEST_ALIAS => versions.last().copied(),
+            DEFAULT_ALIAS => self.defaults.get(name).or(versions.last()).copied(),
+            version => self.find_version(name, version),
+        }
+    }
+
+    /// Highest version with the UUID.
+    fn find_uuid(&self, uuid: &str) -> Option<usize> {
+        self.by_uuid.get(uuid)?
+            .iter()
+            .copied()
+            .max_by(|&a, &b| compare_versions(&self.models[a].version, &self.models[b].version))
+    }
+
+    fn find_version(&self, name: &str, version: &str) -> Option<usize> {
+        self.by_name.get(name)?
+            .iter()
+            .copied()
+            .find(|&index| self.models[index].version == version)
+    }
+}
+
+/// Compare versions so that numeric parts are ordered by value: `1.9 < 1.10`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let chunks = |s: &str| -> Vec<String> {
+        let mut chunks: Vec<String> = Vec::new();
+        for c in s.chars() {
+            match chunks.last_mut() {
+                Some(last) if last.starts_with(|l: char| l.is_ascii_digit()) == c.is_ascii_digit() => last.push(c),
+                _ => chunks.push(c.to_string()),
+            }
+        }
+        chunks
+    };
+    for (x, y) in chunks(a).iter().zip(chunks(b).iter()) {
+        let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    chunks(a).len().cmp(&chunks(b).len())
+}
+
+fn sorted_entries(dir: &Path) -> error::Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|source| MojoError::InvalidPath { path: dir.to_path_buf(), source })?;
+    let mut paths = entries.map(|entry| entry.map(|entry| entry.path())).collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use std::path::Path;
+    use crate::{DaiMojoLibrary, MojoError};
+    use crate::test_support::TempDir;
+    use super::{compare_versions, Registry};
+
+    #[test]
+    fn version_order() {
+        assert_eq!(Ordering::Less, compare_versions("1.9", "1.10"));
+        assert_eq!(Ordering::Less, compare_versions("2", "10"));
+        assert_eq!(Ordering::Less, compare_versions("1.0", "1.0.1"));
+        assert_eq!(Ordering::Greater, compare_versions("v2-b", "v2-a"));
+        assert_eq!(Ordering::Equal, compare_versions("3", "3"));
+    }
+
+    #[test]
+    fn routing() {
+        let root = TempDir::new("routing");
+        let root = root.path();
+        for version in ["1", "2", "10"] {
+            let dir = root.join("iris").join(version);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::copy("data/iris/pipeline.mojo", dir.join("pipeline.mojo")).unwrap();
+        }
+        std::fs::write(root.join("manifest.json"), r#"{"models": [
+            {"name": "flowers", "version": "1", "path": "iris/1/pipeline.mojo"},
+            {"name": "flowers", "version": "2", "path": "iris/2/pipeline.mojo", "default": true},
+            {"name": "flowers", "version": "3", "path": "iris/10/pipeline.mojo"}
+        ]}"#).unwrap();
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let mut registry = Registry::new(&lib, "");
+        registry.load_dir(root).unwrap();
+        registry.load_manifest(root.join("manifest.json")).unwrap();
+        assert_eq!(6, registry.models().len());
+
+        assert_eq!("10", registry.resolve("iris").unwrap().version);
+        assert_eq!("10", registry.resolve("iris@latest").unwrap().version);
+        assert_eq!("2", registry.resolve("iris@2").unwrap().version);
+        assert_eq!("2", registry.resolve("flowers").unwrap().version);
+        assert_eq!("3", registry.resolve("flowers@latest").unwrap().version);
+        let by_uuid = registry.resolve("00000000-0000-0000-0000-000000000000").unwrap();
+        assert_eq!("10", by_uuid.version);
+        assert!(matches!(registry.resolve("iris@4"), Err(MojoError::ModelNotRegistered(_))));
+        assert!(matches!(registry.resolve("wine"), Err(MojoError::ModelNotRegistered(_))));
+        assert!(matches!(registry.register("iris", "2", Path::new("data/iris/pipeline.mojo")), Err(MojoError::DuplicateModel(_))));
+
+        assert!(matches!(registry.register("iris", "latest", Path::new("data/iris/pipeline.mojo")), Err(MojoError::ReservedVersion(_))));
+        assert!(matches!(registry.register("iris", "default", Path::new("data/iris/pipeline.mojo")), Err(MojoError::ReservedVersion(_))));
+        assert!(matches!(registry.register("iris@2", "3", Path::new("data/iris/pipeline.mojo")), Err(MojoError::InvalidModelName(_))));
+
+        // a second default of a name is rejected, not silently replacing the first one
+        std::fs::write(root.join("defaults.json"), r#"{"models": [
+            {"name": "petals", "version": "1", "path": "iris/1/pipeline.mojo", "default": true},
+            {"name": "petals", "version": "2", "path": "iris/2/pipeline.mojo", "default": true}
+        ]}"#).unwrap();
+        assert!(matches!(registry.load_manifest(root.join("defaults.json")), Err(MojoError::DuplicateDefault(name)) if name == "petals"));
+        assert_eq!("1", registry.resolve("petals").unwrap().version);
+
+        // a name hides the same UUID
+        const UUID: &str = "00000000-0000-0000-0000-000000000000";
+        registry.register(UUID, "7", &root.join("iris/1/pipeline.mojo")).unwrap();
+        assert_eq!("7", registry.resolve(UUID).unwrap().version);
+        assert_eq!("7", registry.resolve(&format!("{UUID}@latest")).unwrap().version);
+
+        for spec in ["flowers@1", "iris", UUID] {
+            assert!(registry.route(spec).unwrap().model().is_valid());
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Row oriented scoring API based on serde
+//!
+//! Rows of any [Serialize] type are mapped to input features by their field names,
+//! written into a [RawFrame] batch by batch, and the output columns are turned back into
+//! rows of any [DeserializeOwned] type.
+//! Every feature must be present in each row, possibly as `null` for a missing value;
+//! fields that are not features are an error, unless allowed with [Scorer::with_unknown_fields].
+//! Values are converted to the feature types, like numeric strings to numbers; values that cannot be,
+//! like `7.9` for an integer feature, are an error rather than a missing value.
+
+use std::collections::{HashMap, HashSet};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+use crate::bool_format::BoolFormat;
+use crate::frame_pool::FramePool;
+use crate::daimojo_library::{MOJO_DataType, RawColumnBuffer, RawFrame, RawPipeline};
+use crate::{error, MojoError};
+
+/// Default number of rows passed to the pipeline in one transformation.
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+pub struct Scorer<'a> {
+    pipeline: &'a RawPipeline<'a>,
+    features: Vec<(String, MOJO_DataType)>,
+    outputs: Vec<(String, MOJO_DataType)>,
+    batch_size: usize,
+    pool: Option<&'a FramePool<'a>>,
+    bool_format: BoolFormat,
+    /// Feature names, to recognize unknown fields; `None` when they are allowed
+    known_fields: Option<HashSet<String>>,
+}
+
+impl<'a> Scorer<'a> {
+    pub fn new(pipeline: &'a RawPipeline<'a>) -> Self {
+        let features: Vec<(String, MOJO_DataType)> = pipeline.model.features()
+            .map(|(name, data_type)| (name.into_owned(), data_type))
+            .collect();
+        let outputs = pipeline.outputs()
+            .map(|(name, data_type)| (name.into_owned(), data_type))
+            .collect();
+        let known_fields = Some(features.iter().map(|(name, _)| name.clone()).collect());
+        Self { pipeline, features, outputs, batch_size: DEFAULT_BATCH_SIZE, pool: None, bool_format: BoolFormat::default(), known_fields }
+    }
+
+    /// Set maximal number of rows transformed at once. For 0, the default is used.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = if batch_size == 0 { DEFAULT_BATCH_SIZE } else { batch_size };
+        self
+    }
+
+    /// Set tokens recognized as booleans in string values of boolean features.
+    pub fn with_bool_format(mut self, bool_format: BoolFormat) -> Self {
+        self.bool_format = bool_format;
+        self
+    }
+
+    /// Allow fields that are not features, like identifiers of rows; they are ignored.
+    pub fn with_unknown_fields(mut self, allowed: bool) -> Self {
+        self.known_fields = match allowed {
+            true => None,
+            false => Some(self.features.iter().map(|(name, _)| name.clone()).collect()),
+        };
+        self
+    }
+
+    /// Take frames from the pool instead of allocating them for each call.
+    pub fn with_frame_pool(mut self, pool: &'a FramePool<'a>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Score rows and deserialize each output row into `O`.
+    ///
+    /// Missing values of numeric outputs are presented as `null`, so use `Option` fields to receive them.
+    pub fn score<I: Serialize, O: DeserializeOwned>(&self, rows: &[I]) -> error::Result<Vec<O>> {
+        let mut result = Vec::with_capacity(rows.len());
+        self.score_with(rows, |row| {
+            result.push(serde_json::from_value(Value::Object(row))?);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+
+    /// Score rows and return each output row as a map of column name to value.
+    pub fn score_to_maps<I: Serialize>(&self, rows: &[I]) -> error::Result<Vec<HashMap<String, Value>>> {
+        let mut result = Vec::with_capacity(rows.len());
+        self.score_with(rows, |row| {
+            result.push(row.into_iter().collect());
+            Ok(())
+        })?;
+        Ok(result)
+    }
+
+    fn score_with<I, F>(&self, rows: &[I], mut consume: F) -> error::Result<()>
+        where I: Serialize,
+              F: FnMut(Map<String, Value>) -> error::Result<()>
+    {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let capacity = self.batch_size.min(rows.len());
+        let pooled;
+        let owned;
+        let frame: &RawFrame = match self.pool {
+            Some(pool) => {
+                pooled = pool.get(self.pipeline, capacity)?;
+                &pooled
+            }
+            None => {
+                owned = RawFrame::new(self.pipeline, capacity)?;
+                &owned
+            }
+        };
+        let mut icols = (0..self.features.len())
+            .map(|index| frame.input_col(index))
+            .collect::<error::Result<Vec<_>>>()?;
+        let mut ocols = (0..self.outputs.len())
+            .map(|index| frame.output_col(index))
+            .collect::<error::Result<Vec<_>>>()?;
+        for (batch_index, batch) in rows.chunks(capacity).enumerate() {
+            RawColumnBuffer::reset_current(&mut icols);
+            for (row, item) in batch.iter().enumerate() {
+                let index = batch_index * capacity + row;
+                let item = match serde_json::to_value(item)? {
+                    Value::Object(map) => map,
+                    other => return Err(MojoError::InvalidRow(index, format!("expected a struct or map, found: {other}"))),
+                };
+                check_fields(index, &item, &self.features, self.known_fields.as_ref())?;
+                for ((name, _), col) in self.features.iter().zip(icols.iter_mut()) {
+                    write_value(index, row, col, name, item.get(name), &self.bool_format)?;
+                }
+            }
+
+            self.pipeline.transform(frame, batch.len(), false)?;
+            log::debug!("-- scored batch {batch_index} with {} rows", batch.len());
+
+            RawColumnBuffer::reset_current(&mut ocols);
+            for row in 0..batch.len() {
+                let mut output = Map::new();
+                for ((name, _), col) in self.outputs.iter().zip(ocols.iter_mut()) {
+                    output.insert(name.clone(), read_value(row, col, name)?);
+                }
+                consume(output)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fail on a row lacking a feature, or with a field not in `known_fields`.
+fn check_fields(index: usize, item: &Map<String, Value>, features: &[(String, MOJO_DataType)], known_fields: Option<&HashSet<String>>) -> error::Result<()> {
+    if let Some((name, _)) = features.iter().find(|(name, _)| !item.contains_key(name)) {
+        return Err(MojoError::InvalidRow(index, format!("missing feature '{name}'")));
+    }
+    if let Some(known_fields) = known_fields {
+        if let Some(name) = item.keys().find(|name| !known_fields.contains(*name)) {
+            return Err(MojoError::InvalidRow(index, format!("unknown field '{name}'")));
+        }
+    }
+    Ok(())
+}
+
+/// Write JSON value of the row at `index` into the column, converting it to column's type.
+/// `null` or a missing value is NA; a value that cannot be converted, like a fraction for an integer feature,
+/// fails the row with [MojoError::InvalidRow].
+fn write_value(index: usize, row: usize, col: &mut RawColumnBuffer, name: &str, value: Option<&Value>, bool_format: &BoolFormat) -> error::Result<()> {
+    let value = value.filter(|value| !value.is_null());
+    let data_type = col.data_type;
+    match data_type {
+        MOJO_DataType::MOJO_BOOL => {
+            let value = match value {
+                Some(Value::String(s)) if bool_format.parse_strict(s) == Some(None) => None,
+                value => value,
+            };
+            let value = convert(index, name, data_type, value, |value| value_to_bool(value, bool_format))?;
+            col.write_next(Some(value.unwrap_or(bool_format.na_value)))?;
+        }
+        MOJO_DataType::MOJO_FLOAT => col.write_next(convert(index, name, data_type, value, value_to_f32)?)?,
+        MOJO_DataType::MOJO_DOUBLE => col.write_next(convert(index, name, data_type, value, value_to_f64)?)?,
+        MOJO_DataType::MOJO_INT32 => col.write_next(convert(index, name, data_type, value, value_to_i32)?)?,
+        MOJO_DataType::MOJO_INT64 => col.write_next(convert(index, name, data_type, value, value_to_i64)?)?,
+        MOJO_DataType::MOJO_STRING => {
+            let value = convert(index, name, data_type, value, |value| match value {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+                _ => None,
+            })?;
+            match value {
+                None => col.write_str(row, None)?,
+                Some(s) => col.unchecked_write_str(row, &s),
+            }
+        }
+        MOJO_DataType::MOJO_UNKNOWN => return Err(MojoError::UnsupportedColumnType(name.to_string())),
+    }
+    Ok(())
+}
+
+/// Convert a present value, failing the row at `index` when that is not possible.
+fn convert<T>(index: usize, name: &str, data_type: MOJO_DataType, value: Option<&Value>, convert: impl FnOnce(&Value) -> Option<T>) -> error::Result<Option<T>> {
+    match value {
+        None => Ok(None),
+        Some(value) => convert(value).map(Some)
+            .ok_or_else(|| MojoError::InvalidRow(index, format!("field '{name}': cannot convert {value} to {data_type:?}"))),
+    }
+}
+
+/// Read value from the column as JSON value. NA values are presented as [Value::Null]; booleans have none.
+pub(crate) fn read_value(row: usize, col: &mut RawColumnBuffer, name: &str) -> error::Result<Value> {
+    Ok(match col.data_type {
+        MOJO_DataType::MOJO_BOOL => Value::Bool(col.read_next::<bool>()?.unwrap_or_default()),
+        MOJO_DataType::MOJO_FLOAT => col.read_next::<f32>()?
+            .and_then(|value| Number::from_f64(value as f64))
+            .map_or(Value::Null, Value::Number),
+        MOJO_DataType::MOJO_DOUBLE => col.read_next::<f64>()?
+            .and_then(Number::from_f64)
+            .map_or(Value::Null, Value::Number),
+        MOJO_DataType::MOJO_INT32 => col.read_next::<i32>()?.map_or(Value::Null, Value::from),
+        MOJO_DataType::MOJO_INT64 => col.read_next::<i64>()?.map_or(Value::Null, Value::from),
+        MOJO_DataType::MOJO_STRING => Value::String(col.unchecked_read_string(row).into_owned()),
+        MOJO_DataType::MOJO_UNKNOWN => return Err(MojoError::UnsupportedColumnType(name.to_string())),
+    })
+}
+
+/// Booleans, numbers 0 and 1, and recognized tokens.
+fn value_to_bool(value: &Value, bool_format: &BoolFormat) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Number(n) => match n.as_f64() {
+            Some(0.0) => Some(false),
+            Some(1.0) => Some(true),
+            _ => None,
+        },
+        Value::String(s) => bool_format.parse_strict(s).flatten(),
+        _ => None,
+    }
+}
+
+fn value_to_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+/// Like [value_to_f64], failing for finite values out of the range of `f32`.
+fn value_to_f32(value: &Value) -> Option<f32> {
+    value_to_f64(value).filter(|f| !f.is_finite() || f.abs() <= f32::MAX as f64).map(|f| f as f32)
+}
+
+/// Integers, including numbers with no fractional part, like `7.0`.
+fn value_to_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64().or_else(|| {
+            // 2^63 is exactly representable, so every value below it converts
+            n.as_f64().filter(|f| f.fract() == 0.0 && *f >= i64::MIN as f64 && *f < i64::MAX as f64).map(|f| f as i64)
+        }),
+        Value::String(s) => s.trim().parse().ok(),
+        Value::Bool(b) => Some(*b as i64),
+        _ => None,
+    }
+}
+
+fn value_to_i32(value: &Value) -> Option<i32> {
+    value_to_i64(value).and_then(|i| i32::try_from(i).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use serde_json::json;
+    use crate::{DaiMojoLibrary, MojoError, MOJO_DataType, MOJO_Transform_Ops, RawModel, RawPipeline, Scorer};
+    use super::check_fields;
+
+    #[test]
+    fn fields() {
+        let features = vec![("a".to_string(), MOJO_DataType::MOJO_FLOAT), ("b".to_string(), MOJO_DataType::MOJO_STRING)];
+        let known: HashSet<String> = features.iter().map(|(name, _)| name.clone()).collect();
+        let row = |value: serde_json::Value| value.as_object().unwrap().clone();
+        assert!(check_fields(0, &row(json!({"a": 1.5, "b": null})), &features, Some(&known)).is_ok());
+        let missing = check_fields(3, &row(json!({"a": 1.5})), &features, Some(&known));
+        assert!(matches!(missing, Err(MojoError::InvalidRow(3, reason)) if reason == "missing feature 'b'"));
+        let unknown = check_fields(4, &row(json!({"a": 1.5, "b": "x", "id": 7})), &features, Some(&known));
+        assert!(matches!(unknown, Err(MojoError::InvalidRow(4, reason)) if reason == "unknown field 'id'"));
+        assert!(check_fields(4, &row(json!({"a": 1.5, "b": "x", "id": 7})), &features, None).is_ok());
+    }
+
+    #[test]
+    fn unknown_fields_of_fake_model() {
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, "data/iris/pipeline.mojo", "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        // the fake model has no features
+        let rows = [json!({"id": 1})];
+        let scorer = Scorer::new(&pipeline);
+        assert!(matches!(scorer.score_to_maps(&rows), Err(MojoError::InvalidRow(0, _))));
+        let scorer = scorer.with_unknown_fields(true);
+        assert_eq!(1, scorer.score_to_maps(&rows).unwrap().len());
+    }
+
+    #[test]
+    fn conversions() {
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, crate::test_support::ECHO_MODEL, "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let scorer = Scorer::new(&pipeline);
+        let rows = [
+            json!({"bool": "yes", "int32": 7.0, "int64": "-8", "float": "1.5", "double": true, "string": 12}),
+            json!({"bool": "NA", "int32": null, "int64": null, "float": null, "double": null, "string": null}),
+        ];
+        let outputs = scorer.score_to_maps(&rows).unwrap();
+        let output = |row: usize, name: &str| outputs[row][name].clone();
+        assert_eq!(json!([true, 7, -8, 1.5, 1.0, "12"]),
+                   json!(["bool_out", "int32_out", "int64_out", "float_out", "double_out", "string_out"].map(|name| output(0, name))));
+        assert_eq!(json!([false, null, null, null, null, ""]),
+                   json!(["bool_out", "int32_out", "int64_out", "float_out", "double_out", "string_out"].map(|name| output(1, name))));
+
+        let valid = json!({"bool": false, "int32": 1, "int64": 1, "float": 1, "double": 1, "string": "a"});
+        for (name, value) in [
+            ("bool", json!({})), ("bool", json!("maybe")), ("bool", json!(2)),
+            ("int32", json!(7.9)), ("int32", json!(3e10)), ("int64", json!("abc")),
+            ("float", json!("abc")), ("float", json!(1e300)), ("double", json!([1.0])), ("string", json!({"a": 1})),
+        ] {
+            let mut row = valid.clone();
+            row[name] = value.clone();
+            let result = scorer.score_to_maps(&[valid.clone(), row]);
+            assert!(matches!(&result, Err(MojoError::InvalidRow(1, reason)) if reason.starts_with(&format!("field '{name}': "))),
+                    "{name}={value}: {result:?}");
+        }
+    }
+}
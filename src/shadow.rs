@@ -0,0 +1,201 @@
+//! Shadow scoring: the same rows scored by a primary and a candidate pipeline
+//!
+//! The primary output is what callers get; the candidate output is only recorded,
+//! together with statistics of how much it diverges from the primary one.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::time::Instant;
+use serde::Serialize;
+use serde_json::Value;
+use crate::daimojo_library::{RawColumnBuffer, RawFrame, RawPipeline};
+use crate::scorer::read_value;
+use crate::batch_sizer::BatchSizer;
+use crate::{error, CsvFormat, FlushPolicy, FrameExporter, FrameImporter};
+
+/// Divergence of one output column present in both pipelines.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Divergence {
+    pub rows: usize,
+    /// Rows where the values are not equal
+    pub differing: usize,
+    /// Rows where exactly one of the values is missing
+    pub na_mismatches: usize,
+    /// Largest absolute difference of numeric values
+    pub max_abs_diff: f64,
+    /// Mean absolute difference of numeric values, over rows where both are present
+    pub mean_abs_diff: f64,
+    #[serde(skip)]
+    sum_abs_diff: f64,
+    #[serde(skip)]
+    numeric_rows: usize,
+}
+
+impl Divergence {
+    fn add(&mut self, primary: &Value, candidate: &Value) {
+        self.rows += 1;
+        let abs_diff = match (primary, candidate) {
+            (Value::Null, Value::Null) => return,
+            (Value::Null, _) | (_, Value::Null) => {
+                self.na_mismatches += 1;
+                self.differing += 1;
+                return;
+            }
+            (Value::Bool(a), Value::Bool(b)) => (*a as u8 as f64 - *b as u8 as f64).abs(),
+            (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+                (Some(a), Some(b)) => (a - b).abs(),
+                _ => f64::INFINITY,
+            },
+            (a, b) => {
+                if a != b {
+                    self.differing += 1;
+                }
+                return;
+            }
+        };
+        if abs_diff != 0.0 {
+            self.differing += 1;
+        }
+        self.numeric_rows += 1;
+        self.sum_abs_diff += abs_diff;
+        self.max_abs_diff = self.max_abs_diff.max(abs_diff);
+        self.mean_abs_diff = self.sum_abs_diff / self.numeric_rows as f64;
+    }
+}
+
+/// Statistics of a shadow scoring run.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ShadowReport {
+    pub rows: usize,
+    /// Divergence of output columns by name
+    pub columns: BTreeMap<String, Divergence>,
+    /// Output columns of only one of the pipelines
+    pub primary_only: Vec<String>,
+    pub candidate_only: Vec<String>,
+}
+
+/// Score CSV input with both pipelines, batch by batch.
+/// Output of `primary` is written as CSV to `primary_out`, output of `candidate` to `candidate_out`.
+/// The batch size adapts to the transform time of both pipelines together; both outputs are flushed by `flush_policy`.
+#[allow(clippy::too_many_arguments)]
+pub fn shadow_score<R: Read, P: Write, C: Write, B: BatchSizer>(
+    primary: &RawPipeline,
+    candidate: &RawPipeline,
+    rdr: &mut csv::Reader<R>,
+    mut batch_size: B,
+    format: &CsvFormat,
+    primary_out: P,
+    candidate_out: C,
+    flush_policy: FlushPolicy,
+) -> error::Result<ShadowReport> {
+    let primary_frame = RawFrame::new(primary, batch_size.capacity())?;
+    let candidate_frame = RawFrame::new(candidate, batch_size.capacity())?;
+    let mut primary_importer = FrameImporter::init(primary, &primary_frame, rdr)?
+        .with_bool_format(format.bools.clone());
+    let mut candidate_importer = FrameImporter::init(candidate, &candidate_frame, rdr)?
+        .with_bool_format(format.bools.clone());
+    let mut primary_exporter = FrameExporter::with_writer(primary, &primary_frame, primary_out)?
+        .with_bool_format(format.bools.clone())
+        .with_number_format(format.numbers.clone())
+        .with_flush_policy(flush_policy);
+    let mut candidate_exporter = FrameExporter::with_writer(candidate, &candidate_frame, candidate_out)?
+        .with_bool_format(format.bools.clone())
+        .with_number_format(format.numbers.clone())
+        .with_flush_policy(flush_policy);
+
+    let primary_names: Vec<String> = primary.outputs().map(|(name, _)| name.into_owned()).collect();
+    let candidate_names: Vec<String> = candidate.outputs().map(|(name, _)| name.into_owned()).collect();
+    let mut report = ShadowReport {
+        primary_only: primary_names.iter().filter(|name| !candidate_names.contains(name)).cloned().collect(),
+        candidate_only: candidate_names.iter().filter(|name| !primary_names.contains(name)).cloned().collect(),
+        ..Default::default()
+    };
+    // pairs of output indexes in primary and candidate
+    let common: Vec<(usize, usize)> = primary_names.iter().enumerate()
+        .filter_map(|(index, name)| candidate_names.iter().position(|n| n == name).map(|c| (index, c)))
+        .collect();
+
+    let mut records = Vec::with_capacity(batch_size.current());
+    loop {
+        records.clear();
+        for record in rdr.records().take(batch_size.current()) {
+            records.push(record?);
+        }
+        if records.is_empty() {
+            break;
+        }
+        let rows = primary_importer.import_records(&records)?;
+        candidate_importer.import_records(&records)?;
+        let start = Instant::now();
+        primary.transform(&primary_frame, rows, false)?;
+        candidate.transform(&candidate_frame, rows, false)?;
+        batch_size.update(rows, start.elapsed());
+        for &(primary_index, candidate_index) in &common {
+            let name = &primary_names[primary_index];
+            let mut primary_col = primary_frame.output_col(primary_index)?;
+            let mut candidate_col = candidate_frame.output_col(candidate_index)?;
+            let divergence = report.columns.entry(name.clone()).or_default();
+            add_batch(divergence, rows, name, &mut primary_col, &mut candidate_col)?;
+        }
+        primary_exporter.export_frame(rows)?;
+        candidate_exporter.export_frame(rows)?;
+        report.rows += rows;
+    }
+    primary_exporter.flush()?;
+    candidate_exporter.flush()?;
+    Ok(report)
+}
+
+fn add_batch(divergence: &mut Divergence, rows: usize, name: &str, primary: &mut RawColumnBuffer, candidate: &mut RawColumnBuffer) -> error::Result<()> {
+    for row in 0..rows {
+        let primary_value = read_value(row, primary, name)?;
+        let candidate_value = read_value(row, candidate, name)?;
+        divergence.add(&primary_value, &candidate_value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use serde_json::{json, Value};
+    use crate::{CsvFormat, DaiMojoLibrary, FlushPolicy, MOJO_Transform_Ops, RawModel, RawPipeline};
+    use crate::test_support::FlushCounter;
+    use super::{shadow_score, Divergence};
+
+    #[test]
+    fn divergence() {
+        let mut divergence = Divergence::default();
+        divergence.add(&json!(1.0), &json!(1.0));
+        divergence.add(&json!(1.0), &json!(1.5));
+        divergence.add(&json!(2), &json!(1.5));
+        divergence.add(&Value::Null, &Value::Null);
+        divergence.add(&Value::Null, &json!(3));
+        divergence.add(&json!("a"), &json!("b"));
+        assert_eq!(6, divergence.rows);
+        assert_eq!(4, divergence.differing);
+        assert_eq!(1, divergence.na_mismatches);
+        assert_eq!(0.5, divergence.max_abs_diff);
+        assert_eq!(1.0 / 3.0, divergence.mean_abs_diff);
+    }
+
+    #[test]
+    fn flush_policy() {
+        let lib = DaiMojoLibrary::load(crate::test_support::licensed_fake_runtime()).unwrap();
+        let model = RawModel::load(&lib, "data/iris/pipeline.mojo", "").unwrap();
+        let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops).unwrap();
+        let flushes = |policy: FlushPolicy| {
+            let primary = Rc::new(Cell::new(0));
+            let candidate = Rc::new(Cell::new(0));
+            let mut rdr = csv::Reader::from_reader("a\n1\n2\n3\n4\n5\n6\n".as_bytes());
+            let report = shadow_score(&pipeline, &pipeline, &mut rdr, 3, &CsvFormat::default(),
+                                      FlushCounter(primary.clone()), FlushCounter(candidate.clone()), policy).unwrap();
+            assert_eq!(6, report.rows);
+            // without the header, the final flush and the one when the writer is dropped
+            (primary.get() - 3, candidate.get() - 3)
+        };
+        assert_eq!((6, 6), flushes(FlushPolicy::Line));
+        assert_eq!((2, 2), flushes(FlushPolicy::Batch));
+    }
+}
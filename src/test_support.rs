@@ -0,0 +1,76 @@
+//! Fixtures shared by unit and integration tests
+//!
+//! The fake runtimes are dev-dependencies, so cargo builds them into the same directory as the test executables,
+//! whatever the profile or target directory.
+
+use std::cell::Cell;
+use std::ffi::c_char;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Model of `libempty` with one feature of each type, named after the type, copied to outputs with suffix `_out`.
+pub const ECHO_MODEL: &str = "tests/data/echo.mojo";
+
+/// Path of a fake runtime built by cargo, like `libempty` or `libjustversion`.
+pub fn fake_library(name: &str) -> PathBuf {
+    let exe = std::env::current_exe().expect("path of the test executable");
+    let path = exe.parent().expect("directory of the test executable").join(format!("{name}.so"));
+    assert!(path.is_file(), "{} is missing; it is built as a dev-dependency", path.display());
+    path
+}
+
+/// Path of `libempty`, with a license configured so that it loads models.
+pub fn licensed_fake_runtime() -> PathBuf {
+    let path = fake_library("libempty");
+    license_fake_runtime(&path);
+    path
+}
+
+/// Configures a license in the copy of `libempty` at `path` through its own entry point, leaving the environment alone.
+/// The library stays loaded, so that the license is kept when the tests load and drop it.
+pub fn license_fake_runtime(path: &Path) {
+    let lib = dlopen2::raw::Library::open(path).expect("loading fake runtime");
+    let set_license_key: extern "C" fn(*const c_char) = unsafe { lib.symbol("EMPTY_SetLicenseKey") }.expect("fake runtime entry point");
+    set_license_key(c"fake".as_ptr());
+    std::mem::forget(lib);
+}
+
+/// Empty directory under the target directory, unique to the test process, removed on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let exe = std::env::current_exe().expect("path of the test executable");
+        // the target directory of the profile, above deps
+        let target = exe.parent().and_then(|deps| deps.parent()).expect("target directory");
+        let path = target.join("tmp").join(format!("{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("creating temporary directory");
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writer discarding data, counting flushes reaching it.
+pub struct FlushCounter(pub Rc<Cell<usize>>);
+
+impl Write for FlushCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.set(self.0.get() + 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! Versions of the daimojo runtime and their compatibility
+
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Versions accepted by default; these are served by [BindingSet::V2].
+pub const DEFAULT_RANGE: &str = ">=2.0, <3.0";
+
+/// Version reported by `MOJO_Version()`, like `2.7.11` or `2.99.99 EMPTY`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RuntimeVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Anything following the numbers, like `EMPTY` or `rc1`
+    pub suffix: Option<String>,
+}
+
+impl RuntimeVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self { major, minor, patch, suffix: None }
+    }
+
+    fn numbers(&self) -> (u32, u32, u32) {
+        (self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for RuntimeVersion {
+    type Err = String;
+
+    /// Missing minor and patch numbers are taken as 0.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let end = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+        let (numbers, suffix) = s.split_at(end);
+        let mut parts = numbers.split('.');
+        let mut next = |required: bool| -> Result<u32, String> {
+            match parts.next() {
+                None | Some("") if !required => Ok(0),
+                Some(n) => n.parse().map_err(|_| format!("invalid version '{s}'")),
+                None => Err(format!("invalid version '{s}'")),
+            }
+        };
+        let major = next(true)?;
+        let minor = next(false)?;
+        let patch = next(false)?;
+        let suffix = suffix.trim_start_matches([' ', '-', '+']).trim();
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            suffix: if suffix.is_empty() { None } else { Some(suffix.to_string()) },
+        })
+    }
+}
+
+impl Display for RuntimeVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(suffix) = &self.suffix {
+            write!(f, " {suffix}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Ordered by numbers. Versions with the same numbers and different suffixes are not comparable,
+/// as a suffix like `rc1` or `EMPTY` says nothing about order; this keeps the ordering consistent with equality.
+impl PartialOrd for RuntimeVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.numbers().cmp(&other.numbers()) {
+            Ordering::Equal if self.suffix != other.suffix => None,
+            ordering => Some(ordering),
+        }
+    }
+}
+
+/// Comma separated list of comparisons, all of which must hold, like `>=2.4, <3`.
+#[derive(Clone, Debug)]
+pub struct VersionRange {
+    comparators: Vec<(Ordering, bool, RuntimeVersion)>,
+    text: String,
+}
+
+impl VersionRange {
+    pub fn contains(&self, version: &RuntimeVersion) -> bool {
+        self.comparators.iter().all(|(ordering, or_equal, bound)| {
+            let actual = version.numbers().cmp(&bound.numbers());
+            actual == *ordering || (*or_equal && actual == Ordering::Equal)
+        })
+    }
+}
+
+impl Default for VersionRange {
+    fn default() -> Self {
+        DEFAULT_RANGE.parse().expect("default range is valid")
+    }
+}
+
+impl FromStr for VersionRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut comparators = Vec::new();
+        for item in s.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (op, version) = match item.find(|c: char| c.is_ascii_digit()) {
+                Some(index) => item.split_at(index),
+                None => return Err(format!("missing version in '{item}'")),
+            };
+            let (ordering, or_equal) = match op.trim() {
+                ">=" => (Ordering::Greater, true),
+                ">" => (Ordering::Greater, false),
+                "<=" => (Ordering::Less, true),
+                "<" => (Ordering::Less, false),
+                "=" | "" => (Ordering::Equal, true),
+                op => return Err(format!("unknown operator '{op}' in '{item}'")),
+            };
+            comparators.push((ordering, or_equal, version.parse()?));
+        }
+        Ok(Self { comparators, text: s.to_string() })
+    }
+}
+
+impl Display for VersionRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Set of bindings used to talk to the runtime.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BindingSet {
+    /// Pipeline and frame based API of daimojo 2.x
+    V2,
+}
+
+/// Known facts about a range of runtime versions.
+pub struct Compatibility {
+    pub range: &'static str,
+    /// Matches only versions with this suffix
+    pub suffix: Option<&'static str>,
+    pub bindings: BindingSet,
+    pub quirks: &'static [&'static str],
+}
+
+/// Compatibility table; the first matching entry applies.
+pub const COMPATIBILITY: &[Compatibility] = &[
+    Compatibility {
+        range: ">=2.0, <3.0",
+        suffix: Some("EMPTY"),
+        bindings: BindingSet::V2,
+        quirks: &["fake runtime (libempty): models have no features and no outputs"],
+    },
+    Compatibility {
+        range: ">=2.0, <3.0",
+        suffix: None,
+        bindings: BindingSet::V2,
+        quirks: &["INT32 outputs computed from NA inputs can be MOJO_INT32_NAN-2 instead of MOJO_INT32_NAN"],
+    },
+];
+
+/// Find compatibility entry for given version.
+pub fn compatibility(version: &RuntimeVersion) -> Option<&'static Compatibility> {
+    COMPATIBILITY.iter().find(|entry| {
+        let range: VersionRange = entry.range.parse().expect("compatibility table is valid");
+        let suffix_matches = match entry.suffix {
+            None => true,
+            Some(suffix) => version.suffix.as_deref() == Some(suffix),
+        };
+        suffix_matches && range.contains(version)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    use super::{compatibility, BindingSet, RuntimeVersion, VersionRange};
+
+    #[test]
+    fn parse_versions() {
+        let v: RuntimeVersion = "2.99.99 EMPTY".parse().unwrap();
+        assert_eq!((2, 99, 99, Some("EMPTY")), (v.major, v.minor, v.patch, v.suffix.as_deref()));
+        assert_eq!("2.99.99 EMPTY", v.to_string());
+        let v: RuntimeVersion = "2.7".parse().unwrap();
+        assert_eq!(RuntimeVersion::new(2, 7, 0), v);
+        let v: RuntimeVersion = "2.8.1-rc1".parse().unwrap();
+        assert_eq!(Some("rc1"), v.suffix.as_deref());
+        assert!("JUST VERSION".parse::<RuntimeVersion>().is_err());
+        assert!(RuntimeVersion::new(2, 10, 0) > RuntimeVersion::new(2, 9, 9));
+        let rc: RuntimeVersion = "2.8.1-rc1".parse().unwrap();
+        assert_eq!(None, rc.partial_cmp(&RuntimeVersion::new(2, 8, 1)));
+        assert_ne!(rc, RuntimeVersion::new(2, 8, 1));
+        assert_eq!(Some(Ordering::Equal), rc.partial_cmp(&rc.clone()));
+        assert!(rc < RuntimeVersion::new(2, 8, 2));
+    }
+
+    #[test]
+    fn ranges() {
+        let range = VersionRange::default();
+        assert!(range.contains(&RuntimeVersion::new(2, 0, 0)));
+        assert!(range.contains(&"2.99.99 EMPTY".parse().unwrap()));
+        assert!(!range.contains(&RuntimeVersion::new(3, 0, 0)));
+        assert!(!range.contains(&RuntimeVersion::new(1, 9, 9)));
+        let range: VersionRange = ">2.4.1,<=2.6".parse().unwrap();
+        assert!(!range.contains(&RuntimeVersion::new(2, 4, 1)));
+        assert!(range.contains(&RuntimeVersion::new(2, 6, 0)));
+        assert!(!range.contains(&RuntimeVersion::new(2, 6, 1)));
+        assert!("~2".parse::<VersionRange>().is_err());
+    }
+
+    #[test]
+    fn compatibility_table() {
+        let fake = compatibility(&"2.99.99 EMPTY".parse().unwrap()).unwrap();
+        assert_eq!(Some("EMPTY"), fake.suffix);
+        let real = compatibility(&RuntimeVersion::new(2, 7, 11)).unwrap();
+        assert_eq!(BindingSet::V2, real.bindings);
+        assert_eq!(None, real.suffix);
+        assert!(compatibility(&RuntimeVersion::new(3, 0, 0)).is_none());
+    }
+}
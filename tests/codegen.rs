@@ -0,0 +1,28 @@
+//! Compiles code generated by `daimojo::codegen` and uses it with the fake runtime
+
+use daimojo::{DaiMojoLibrary, MojoError, MOJO_Transform_Ops, RawModel, RawPipeline};
+
+/// Kept equal to the generator output by its unit test
+#[path = "codegen/wine_like.rs"]
+mod wine_like;
+
+#[test]
+fn generated_scorer() -> anyhow::Result<()> {
+    let input = wine_like::Input { fixed_acidity: Some(7.4), count_2: Some("a".to_string()), type_: Some(1.5), ..Default::default() };
+    let row = serde_json::to_value(&input)?;
+    assert_eq!(Some(&serde_json::json!(7.4f32)), row.get("fixed acidity"));
+    assert_eq!(Some(&serde_json::json!("a")), row.get("Count"));
+    assert_eq!(Some(&serde_json::Value::Null), row.get("count"));
+    assert_eq!(Some(&serde_json::json!(1.5)), row.get("type"));
+    let output: wine_like::Output = serde_json::from_value(serde_json::json!({"quality.3": 0.5, "label": "good", "good": true}))?;
+    assert_eq!((Some(0.5), "good", true), (output.quality_3, output.label.as_str(), output.good));
+
+    let lib = DaiMojoLibrary::load(daimojo::test_support::licensed_fake_runtime())?;
+    let model = RawModel::load(&lib, "data/iris/pipeline.mojo", "")?;
+    let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops)?;
+    let scorer = wine_like::PipelineScorer::new(&pipeline);
+    assert!(scorer.score(&[])?.is_empty());
+    // the fake model has no features, so these are unknown to it
+    assert!(matches!(scorer.score(&[input]), Err(MojoError::InvalidRow(0, _))));
+    Ok(())
+}
@@ -0,0 +1,42 @@
+// Generated by daimojo codegen from pipeline uuid - do not edit.
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct Input {
+    #[serde(rename = "fixed acidity")]
+    pub fixed_acidity: Option<f32>,
+    pub count: Option<i32>,
+    #[serde(rename = "Count")]
+    pub count_2: Option<String>,
+    pub red: Option<bool>,
+    pub id: Option<i64>,
+    #[serde(rename = "type")]
+    pub type_: Option<f64>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Output {
+    #[serde(rename = "quality.3")]
+    pub quality_3: Option<f32>,
+    pub label: String,
+    pub good: bool,
+}
+
+/// Scorer of the pipeline uuid, with typed rows.
+pub struct PipelineScorer<'a> {
+    scorer: daimojo::Scorer<'a>,
+}
+
+impl<'a> PipelineScorer<'a> {
+    pub fn new(pipeline: &'a daimojo::RawPipeline<'a>) -> Self {
+        Self::with_scorer(daimojo::Scorer::new(pipeline))
+    }
+
+    /// Use a configured scorer, like one with a frame pool.
+    pub fn with_scorer(scorer: daimojo::Scorer<'a>) -> Self {
+        Self { scorer }
+    }
+
+    pub fn score(&self, rows: &[Input]) -> daimojo::Result<Vec<Output>> {
+        self.scorer.score(rows)
+    }
+}
@@ -0,0 +1,43 @@
+use std::path::Path;
+use std::process::Command;
+use daimojo::license::{LICENSE_ENV_VARS, LICENSE_FILE_VAR, LICENSE_KEY_VAR};
+use daimojo::{DaiMojoLibrary, License, MojoError, RawModel};
+
+const MOJO: &str = "data/iris/pipeline.mojo";
+
+#[test]
+fn license_with_fake_runtime() -> anyhow::Result<()> {
+    for var in LICENSE_ENV_VARS {
+        std::env::remove_var(var);
+    }
+    // fake runtime which accepts any model, when a license is configured
+    let fake_lib = daimojo::test_support::fake_library("libempty");
+    let lib = DaiMojoLibrary::load(&fake_lib)?;
+
+    // no license
+    assert!(matches!(RawModel::load(&lib, MOJO, ""), Err(MojoError::InvalidModel(_))));
+    let output = Command::new(env!("CARGO_BIN_EXE_daimojo"))
+        .arg("--lib").arg(&fake_lib)
+        .args(["--mojo", MOJO, "license-check"])
+        .output()?;
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("* License: not found"));
+
+    // explicit key
+    let license = License::locate(Some(Path::new("Cargo.toml")), None)?.unwrap();
+    assert_eq!(vec![(LICENSE_FILE_VAR, Some("Cargo.toml".into())), (LICENSE_KEY_VAR, None)], license.env_vars());
+    let license = License::locate(None, Some("secret"))?.unwrap();
+    assert_eq!(vec![(LICENSE_KEY_VAR, Some("secret".into())), (LICENSE_FILE_VAR, None)], license.env_vars());
+    // the only test of this binary, so no other thread reads the environment
+    std::env::set_var(LICENSE_KEY_VAR, "secret");
+    let model = RawModel::load(&lib, MOJO, "")?;
+    assert!(model.is_valid());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_daimojo"))
+        .arg("--lib").arg(&fake_lib)
+        .args(["--mojo", MOJO, "license-check"])
+        .output()?;
+    assert_eq!(Some(0), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("(environment)"));
+    Ok(())
+}
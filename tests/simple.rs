@@ -1,6 +1,6 @@
 extern crate core;
 
-use daimojo::{DaiMojoLibrary, FrameExporter, FrameImporter, MOJO_INT32_NAN, MOJO_Transform_Ops, RawFrame, RawModel, RawPipeline};
+use daimojo::{DaiMojoLibrary, FrameExporter, FrameImporter, MOJO_INT32_NAN, MOJO_Transform_Ops, RawFrame, RawModel, RawPipeline, Scorer};
 use daimojo::MOJO_DataType::{MOJO_DOUBLE, MOJO_INT32};
 
 const LIB: &str = "libdaimojo.so";
@@ -108,14 +108,21 @@ fn simple_predict_csv() -> anyhow::Result<()> {
     pipeline.transform(&frame, 0, false)?;
 
     let mut v1 = frame.output_col(0)?;
-    assert_eq!(6, v1.unchecked_read_next());
-    assert_eq!(66, v1.unchecked_read_next());
-    assert_eq!(/*TODO:7*/MOJO_INT32_NAN-2/*TODO OMG why -2?*/, v1.unchecked_read_next());
+    assert_eq!(6, v1.unchecked_read_next::<i32>());
+    assert_eq!(66, v1.unchecked_read_next::<i32>());
+    assert_eq!(/*TODO:7*/MOJO_INT32_NAN-2/*TODO OMG why -2?*/, v1.unchecked_read_next::<i32>());
+
+    // with explicit NA, the near-sentinel shows up as a real value, not as a missing one
+    let mut v1 = frame.output_col(0)?;
+    assert_eq!(Some(6), v1.read_next::<i32>()?);
+    assert_eq!(Some(66), v1.read_next::<i32>()?);
+    assert_eq!(Some(2147483645), v1.read_next::<i32>()?);
+    assert!(v1.read_next::<f64>().is_err());
 
     let mut v2 = frame.output_col(1)?;
-    assert_eq!(15.0, v2.unchecked_read_next());
-    assert_eq!(165.0, v2.unchecked_read_next());
-    assert_eq!(18.6, v2.unchecked_read_next());
+    assert_eq!(15.0, v2.unchecked_read_next::<f64>());
+    assert_eq!(165.0, v2.unchecked_read_next::<f64>());
+    assert_eq!(18.6, v2.unchecked_read_next::<f64>());
 
 
     // export batch
@@ -127,3 +134,30 @@ fn simple_predict_csv() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn simple_predict_serde() -> anyhow::Result<()> {
+    #[derive(serde::Serialize)]
+    struct Input { a: i32, a2: i32, a3: Option<i32>, b: f64, b2: f64, b3: f64 }
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Output { v1: Option<i32>, v2: f64 }
+
+    let lib = DaiMojoLibrary::load(LIB)?;
+    let model = RawModel::load(&lib, SIMPLE_PIPELINE_MOJO, "")?;
+    let pipeline = RawPipeline::new(&model, MOJO_Transform_Ops::PREDICT as MOJO_Transform_Ops)?;
+
+    let scorer = Scorer::new(&pipeline).with_batch_size(2);
+    let rows = [
+        Input { a: 1, a2: 2, a3: Some(3), b: 4.0, b2: 5.0, b3: 6.0 },
+        Input { a: 11, a2: 22, a3: Some(33), b: 44.0, b2: 55.0, b3: 66.0 },
+        Input { a: 1, a2: 2, a3: None, b: 6.1, b2: 6.2, b3: 6.3 },
+    ];
+    let outputs: Vec<Output> = scorer.score(&rows)?;
+    assert_eq!(Output { v1: Some(6), v2: 15.0 }, outputs[0]);
+    assert_eq!(Output { v1: Some(66), v2: 165.0 }, outputs[1]);
+    assert_eq!(3, outputs.len());
+
+    let maps = scorer.score_to_maps(&rows[..1])?;
+    assert_eq!(Some(&serde_json::json!(6)), maps[0].get("v1"));
+    Ok(())
+}